@@ -0,0 +1,74 @@
+//! Loads and renders arbitrary templates, which must fail with an error rather than panic.
+//!
+//! The first byte of the input picks the environment settings, the rest is the template. The
+//! template can include, extend or import the fixed templates below as well as itself.
+//!
+//! Run with `cargo fuzz run render` from the repository root.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use temple::value::{Value, ValuesMap};
+use temple::{MemoryFileSystem, Template, TemplateEnv, UndefinedBehavior};
+
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "base.j2",
+        "<title>{% block title %}Base{% endblock %}</title>{% block body required %}{% endblock %}",
+    ),
+    (
+        "macros.j2",
+        "{% macro item(value, sep=', ') %}{{ value }}{{ sep }}{{ varargs }}{{ kwargs }}\
+         {% endmacro %}{% macro wrap() %}[{{ caller() }}]{% endmacro %}{% set title = 'Macros' %}",
+    ),
+    ("item.j2", "<{{ item | default(name) | e }}>"),
+];
+
+fuzz_target!(|input: &[u8]| {
+    let (flags, body) = match input.split_first() {
+        Some((flags, body)) => (*flags, body),
+        None => return,
+    };
+    let body = match std::str::from_utf8(body) {
+        Ok(body) => body,
+        Err(_) => return,
+    };
+
+    let mut env = TemplateEnv::default();
+    let settings = env.settings_mut();
+    settings.extensions.do_ext = true;
+    settings.extensions.loop_controls = true;
+    settings.autoescape = flags & 1 != 0;
+    settings.use_line_statements = flags & 2 != 0;
+    settings.trim_blocks = flags & 4 != 0;
+    settings.lstrip_blocks = flags & 8 != 0;
+    if flags & 16 != 0 {
+        settings.undefined = UndefinedBehavior::Lenient;
+    }
+    let mut handler = MemoryFileSystem::new();
+    for (name, content) in TEMPLATES {
+        handler.add_file(name.to_string(), content.to_string());
+    }
+    handler.add_file("self.j2".to_string(), body.to_string());
+    if env.add_filesystem_handler(Box::new(handler)).is_err() {
+        return;
+    }
+
+    let mut template = match Template::new(&env) {
+        Ok(template) => template,
+        Err(_) => return,
+    };
+    if template.load(body).is_err() {
+        return;
+    }
+    let mut params = ValuesMap::default();
+    params.insert("name".to_string(), Value::String("<world>".to_string()));
+    params.insert(
+        "items".to_string(),
+        Value::ValuesList(vec![
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(3),
+        ]),
+    );
+    let _ = template.render_as_string(params);
+});
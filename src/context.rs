@@ -1,28 +1,89 @@
-use crate::error::{Error, ParseError, ParseErrorKind, Result};
+use crate::error::{Error, ParseError, ParseErrorKind, RenderErrorKind, Result};
 use crate::source::SourceLocationInfo;
-use crate::value::{Value, ValuesMap};
-use crate::TemplateEnv;
+use crate::stack;
+use crate::template::{Template, TemplateFrame};
+use crate::value::{CallArgs, Callable, Value, ValuesMap};
+use crate::{TemplateEnv, UndefinedBehavior};
 use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 
+static NEXT_CALLABLE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Template code (blocks, macros...) that can be invoked through a callable value.
+pub(crate) trait TemplateCallable<'a> {
+    fn call(&self, args: CallArgs, context: &Context<'a>) -> Result<Value>;
+}
+
+/// Early exit requested from a loop body by `{% break %}` or `{% continue %}`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LoopControl {
+    Break,
+    Continue,
+}
+
+/// State shared by every context that takes part in the same render.
+#[derive(Default)]
+struct RenderState<'a> {
+    callables: RefCell<HashMap<usize, Rc<dyn TemplateCallable<'a> + 'a>>>,
+    templates: RefCell<Vec<Rc<Template<'a>>>>,
+    loop_control: Cell<Option<LoopControl>>,
+    /// Position of the stack when the render started.
+    stack_base: usize,
+    /// Stack space the render may use, from the settings.
+    stack_budget: usize,
+}
+
 #[derive(Clone)]
 pub struct Context<'a> {
     global_scope: Arc<RwLock<ValuesMap>>,
     external_scope: ValuesMap,
     scopes: Vec<Arc<RwLock<ValuesMap>>>,
     callback_renderer: &'a TemplateEnv<'a>,
+    render_state: Rc<RenderState<'a>>,
+    template_frame: Option<Rc<TemplateFrame<'a>>>,
+    autoescape: bool,
 }
 
 impl<'a> Context<'a> {
-    pub fn new(external_scope: impl Serialize, callback_renderer: &'a TemplateEnv<'_>) -> Self {
-        let v = serde_json::to_value(&external_scope).unwrap();
-        let external_scope: ValuesMap = serde_json::from_value(v).unwrap();
+    /// Creates the context of a render. The parameters must serialize to a map, otherwise this
+    /// fails with `RenderErrorKind::InvalidValueType`.
+    pub fn new(
+        external_scope: impl Serialize,
+        callback_renderer: &'a TemplateEnv<'_>,
+    ) -> Result<Self> {
+        let external_scope: ValuesMap = serde_json::to_value(&external_scope)
+            .and_then(serde_json::from_value)
+            .map_err(|_| Error::from(RenderErrorKind::InvalidValueType))?;
 
-        Self {
+        Ok(Self {
             global_scope: Arc::new(RwLock::new(ValuesMap::default())),
             external_scope,
             scopes: vec![],
             callback_renderer,
+            render_state: Rc::new(RenderState {
+                stack_base: stack::position(),
+                stack_budget: callback_renderer.settings().stack_budget,
+                ..RenderState::default()
+            }),
+            template_frame: None,
+            autoescape: callback_renderer.settings().autoescape,
+        })
+    }
+    /// Returns a context sharing the render state but none of the variables
+    /// apart from the globals.
+    pub(crate) fn isolated(&self) -> Self {
+        Self {
+            global_scope: self.global_scope.clone(),
+            external_scope: ValuesMap::default(),
+            scopes: vec![],
+            callback_renderer: self.callback_renderer,
+            render_state: self.render_state.clone(),
+            template_frame: None,
+            autoescape: self.autoescape,
         }
     }
     pub fn enter_scope(&mut self) -> Arc<RwLock<ValuesMap>> {
@@ -51,10 +112,121 @@ impl<'a> Context<'a> {
             )))
         }
     }
+    /// Stores `value` in the innermost scope.
+    pub(crate) fn set(&mut self, key: String, value: Value) {
+        let scope = match self.scopes.last() {
+            Some(scope) => scope.clone(),
+            None => self.enter_scope(),
+        };
+        scope.write().unwrap().insert(key, value);
+    }
+    /// Replaces the value of a variable where it is defined, as modifying it in place would.
+    pub(crate) fn update(&mut self, key: String, value: Value) {
+        for scope in self.scopes.iter().rev() {
+            let mut scope = scope.write().unwrap();
+            if let Some(current) = scope.get_mut(&key) {
+                *current = value;
+                return;
+            }
+        }
+        // Variables passed to the render or global ones are shadowed from the outermost scope.
+        let scope = match self.scopes.first() {
+            Some(scope) => scope.clone(),
+            None => self.enter_scope(),
+        };
+        scope.write().unwrap().insert(key, value);
+    }
     pub fn set_global(&mut self, global_scope: Arc<RwLock<ValuesMap>>) {
         self.global_scope = global_scope;
     }
     pub fn get_renderer_callback(&self) -> &'a TemplateEnv<'a> {
         self.callback_renderer
     }
+    pub(crate) fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+    /// Drops every scope entered after the given depth.
+    pub(crate) fn truncate_scopes(&mut self, depth: usize) {
+        self.scopes.truncate(depth);
+    }
+    pub(crate) fn template_frame(&self) -> Option<&Rc<TemplateFrame<'a>>> {
+        self.template_frame.as_ref()
+    }
+    pub(crate) fn set_template_frame(&mut self, frame: Rc<TemplateFrame<'a>>) {
+        self.template_frame = Some(frame);
+    }
+    /// Keeps a template loaded during the render alive until the render is over,
+    /// so the values created from it remain valid.
+    pub(crate) fn retain_template(&self, template: Rc<Template<'a>>) {
+        self.render_state.templates.borrow_mut().push(template);
+    }
+    /// Tells whether undefined values evaluate to an empty value instead of failing the render.
+    pub(crate) fn lenient_undefined(&self) -> bool {
+        self.callback_renderer.settings().undefined == UndefinedBehavior::Lenient
+    }
+    pub(crate) fn autoescape(&self) -> bool {
+        self.autoescape
+    }
+    pub(crate) fn set_autoescape(&mut self, autoescape: bool) {
+        self.autoescape = autoescape;
+    }
+    /// Turns the output rendered by template code into a value, marked safe when autoescaping
+    /// so that it is not escaped once more.
+    pub(crate) fn output_value(&self, out: Vec<u8>) -> Value {
+        let output = String::from_utf8_lossy(&out).into_owned();
+        if self.autoescape {
+            Value::SafeString(output)
+        } else {
+            Value::String(output)
+        }
+    }
+    /// Requests the innermost loop to stop rendering its current iteration.
+    pub(crate) fn set_loop_control(&self, control: LoopControl) {
+        self.render_state.loop_control.set(Some(control));
+    }
+    /// Tells whether the renderers of the current loop body should be skipped.
+    pub(crate) fn loop_control(&self) -> Option<LoopControl> {
+        self.render_state.loop_control.get()
+    }
+    pub(crate) fn take_loop_control(&self) -> Option<LoopControl> {
+        self.render_state.loop_control.take()
+    }
+    /// Wraps template code into a value which can be called from expressions.
+    pub(crate) fn register_callable(
+        &self,
+        name: &str,
+        callable: Rc<dyn TemplateCallable<'a> + 'a>,
+    ) -> Value {
+        let id = NEXT_CALLABLE_ID.fetch_add(1, Ordering::Relaxed);
+        self.render_state
+            .callables
+            .borrow_mut()
+            .insert(id, callable);
+        Value::Callable(Callable::new(name, move |args, context| {
+            context.call_registered(id, args)
+        }))
+    }
+    fn call_registered(&self, id: usize, args: CallArgs) -> Result<Value> {
+        let callable = self.render_state.callables.borrow().get(&id).cloned();
+        match callable {
+            Some(callable) => {
+                self.check_stack()?;
+                callable.call(args, self)
+            }
+            None => Err(Error::from(RenderErrorKind::InvalidOperation)),
+        }
+    }
+    /// Fails once the render used up its stack budget, as with an endless recursion.
+    pub(crate) fn check_stack(&self) -> Result<()> {
+        if stack::exhausted(self.render_state.stack_base, self.render_state.stack_budget) {
+            Err(Error::from(RenderErrorKind::RecursionTooDeep))
+        } else {
+            Ok(())
+        }
+    }
+    /// Releases everything registered while rendering.
+    pub(crate) fn release(&self) {
+        self.render_state.callables.borrow_mut().clear();
+        self.render_state.templates.borrow_mut().clear();
+    }
 }
@@ -1,4 +1,5 @@
 use crate::source::SourceLocationInfo;
+use std::convert::Infallible;
 use std::io;
 
 use thiserror::Error as ThisError;
@@ -22,6 +23,25 @@ pub struct ParseError {
     pub kind: ParseErrorKind,
 }
 
+impl Error {
+    /// Tells whether the error comes from looking up a value which does not exist.
+    pub fn is_undefined(&self) -> bool {
+        matches!(
+            self,
+            Error::ParseError(ParseError {
+                kind: ParseErrorKind::UndefinedValue(_),
+                ..
+            }) | Error::RenderError(RenderErrorKind::IndexOutOfRange)
+        )
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
 impl ParseError {
     pub fn new(kind: ParseErrorKind, location: Option<SourceLocationInfo>) -> Self {
         Self {
@@ -84,6 +104,16 @@ pub enum ParseErrorKind {
     UnexpectedRawEnd,
     #[error("Unknown filter: {0}")]
     UnknownFilter(String),
+    #[error("Unknown test: {0}{suggestion}", suggestion = did_you_mean(.1))]
+    UnknownTest(String, Option<String>),
+    #[error("Block '{0}' defined twice")]
+    DuplicateBlock(String),
+    #[error("Maximum nesting depth exceeded")]
+    NestingTooDeep,
+    #[error("'{0}' used outside of a loop")]
+    LoopControlOutsideLoop(&'static str),
+    #[error("Statement not closed, '{0}' expected")]
+    UnclosedStatement(&'static str),
 }
 
 #[non_exhaustive]
@@ -97,7 +127,34 @@ pub enum RenderErrorKind {
     InvalidOperation,
     #[error("Invalid type of the value in the particular context")]
     InvalidValueType,
+    #[error("Template extended multiple times")]
+    ExtendedMultipleTimes,
+    #[error("Required block '{0}' not found")]
+    RequiredBlockNotFound(String),
+    #[error("Cannot unpack {0} values into {1} variables")]
+    UnpackMismatch(usize, usize),
+    #[error("Index out of range")]
+    IndexOutOfRange,
+    #[error("Missing argument '{0}'")]
+    MissingArgument(String),
+    #[error("Multiple values for argument '{0}'")]
+    MultipleValues(String),
+    #[error("Too many arguments for '{0}'")]
+    TooManyArguments(String),
+    #[error("Maximum recursion depth exceeded")]
+    RecursionTooDeep,
+    #[error("Value nested too deeply")]
+    ValueTooDeep,
+    #[error("Value too long")]
+    ValueTooLong,
 }
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    suggestion
+        .as_ref()
+        .map(|name| format!(". Did you mean '{}'?", name))
+        .unwrap_or_default()
+}
+
 impl ParseError {
     pub fn set_location(&mut self, location: SourceLocationInfo) {
         self.location = location;
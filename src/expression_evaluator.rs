@@ -1,9 +1,10 @@
 use crate::context::Context;
-use crate::error::{Error, Result};
+use crate::error::{Error, RenderErrorKind, Result};
 use crate::filters::FilterExpression;
 use crate::renderer::Render;
+use crate::testers::TesterExpression;
 use crate::value::visitors;
-use crate::value::{Value, ValuesList, ValuesMap};
+use crate::value::{CallArgs, Value, ValuesList, ValuesMap};
 use std::collections::HashMap;
 use std::io::Write;
 
@@ -27,6 +28,8 @@ pub enum BinaryOperation {
     LogicalGt,
     LogicalGe,
     LogicalLe,
+    In,
+    NotIn,
     StringConcat,
 }
 
@@ -38,7 +41,20 @@ pub enum UnaryOperation {
 }
 pub struct SubscriptExpression<'a> {
     expression: Box<Expression<'a>>,
-    subscript_expression: Vec<Box<dyn Evaluate + 'a>>,
+    subscript_expression: Vec<Subscript<'a>>,
+}
+/// An item access of a subscript expression.
+pub enum Subscript<'a> {
+    /// `value[index]`
+    Index(Box<dyn Evaluate + 'a>),
+    /// `value.name`, which may also name a method of the value
+    Attribute(String),
+    /// Start, stop and step of `value[start:stop:step]`
+    Slice(
+        Option<Box<dyn Evaluate + 'a>>,
+        Option<Box<dyn Evaluate + 'a>>,
+        Option<Box<dyn Evaluate + 'a>>,
+    ),
 }
 #[derive(Default)]
 pub struct TupleExpression<'a> {
@@ -51,11 +67,11 @@ impl<'a> TupleExpression<'a> {
 }
 impl Evaluate for TupleExpression<'_> {
     fn evaluate(&self, values: Context<'_>) -> Result<Value> {
-        let tuple: ValuesList = self
+        let tuple = self
             .expressions
             .iter()
-            .map(|expr| expr.evaluate(values.clone()).unwrap())
-            .collect();
+            .map(|expr| expr.evaluate(values.clone()))
+            .collect::<Result<ValuesList>>()?;
         Ok(Value::ValuesList(tuple))
     }
 }
@@ -103,6 +119,27 @@ impl Evaluate for FilteredExpression<'_> {
     }
 }
 
+pub struct TestedExpression<'a> {
+    expression: Box<dyn Evaluate + 'a>,
+    tester: TesterExpression<'a>,
+}
+impl<'a> TestedExpression<'a> {
+    pub fn new(expression: Box<dyn Evaluate + 'a>, tester: TesterExpression<'a>) -> Self {
+        Self { expression, tester }
+    }
+}
+
+impl Evaluate for TestedExpression<'_> {
+    fn evaluate(&self, values: Context<'_>) -> Result<Value> {
+        let value = match self.expression.evaluate(values.clone()) {
+            Ok(value) => value,
+            Err(err) if err.is_undefined() => Value::Empty,
+            Err(err) => return Err(err),
+        };
+        Ok(Value::Boolean(self.tester.test(value, values)?))
+    }
+}
+
 pub enum Expression<'a> {
     Constant(Value),
     Binary(BinaryOperation, Box<Expression<'a>>, Box<Expression<'a>>),
@@ -110,8 +147,16 @@ pub enum Expression<'a> {
     Subscript(SubscriptExpression<'a>),
     ValueRef(ValueRefExpression),
     Filtered(FilteredExpression<'a>),
+    Tested(TestedExpression<'a>),
     Tuple(TupleExpression<'a>),
     Dict(DictionaryExpression<'a>),
+    Call(CallExpression<'a>),
+    /// Condition, value and optional `else` value of `value if condition else other`
+    Conditional(
+        Box<Expression<'a>>,
+        Box<Expression<'a>>,
+        Option<Box<Expression<'a>>>,
+    ),
 }
 impl ValueRefExpression {
     pub fn new(identifier: String) -> Self {
@@ -120,7 +165,10 @@ impl ValueRefExpression {
 }
 impl Evaluate for ValueRefExpression {
     fn evaluate(&self, values: Context<'_>) -> Result<Value> {
-        values.find(&self.identifier)
+        match values.find(&self.identifier) {
+            Err(err) if err.is_undefined() && values.lenient_undefined() => Ok(Value::Empty),
+            result => result,
+        }
     }
 }
 
@@ -132,43 +180,187 @@ impl<'a> SubscriptExpression<'a> {
             subscript_expression,
         }
     }
-    pub fn add_index(&mut self, subscript: Box<dyn Evaluate + 'a>) {
+    pub fn add_index(&mut self, subscript: Subscript<'a>) {
         self.subscript_expression.push(subscript);
     }
 }
 impl Evaluate for SubscriptExpression<'_> {
     fn evaluate(&self, values: Context<'_>) -> Result<Value> {
         let mut cur = self.expression.evaluate(values.clone())?;
-        for idx in &self.subscript_expression {
-            let subscript = idx.evaluate(values.clone())?;
-            cur = visitors::Subscription::apply(cur, subscript);
+        for subscript in &self.subscript_expression {
+            cur = match subscript.apply(cur, values.clone()) {
+                Err(err) if err.is_undefined() && values.lenient_undefined() => Value::Empty,
+                result => result?,
+            };
         }
 
         Ok(cur)
     }
 }
+impl Subscript<'_> {
+    /// The item an index or attribute refers to. Slices don't refer to a single item.
+    fn key(&self, values: Context<'_>) -> Result<Value> {
+        match self {
+            Subscript::Index(index) => index.evaluate(values),
+            Subscript::Attribute(name) => Ok(Value::String(name.clone())),
+            Subscript::Slice(..) => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+    fn apply(&self, value: Value, values: Context<'_>) -> Result<Value> {
+        match self {
+            Subscript::Index(..) | Subscript::Attribute(..) => {
+                visitors::Subscription::apply(value, self.key(values)?)
+            }
+            Subscript::Slice(start, stop, step) => {
+                let bound = |bound: &Option<Box<dyn Evaluate + '_>>| match bound {
+                    Some(bound) => match bound.evaluate(values.clone())? {
+                        Value::Integer(bound) => Ok(Some(bound)),
+                        Value::None => Ok(None),
+                        _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+                    },
+                    None => Ok(None),
+                };
+                visitors::Subscription::slice(value, bound(start)?, bound(stop)?, bound(step)?)
+            }
+        }
+    }
+}
+impl SubscriptExpression<'_> {
+    /// Calls `value.method(...)` when the last subscript names a method of the value. A value
+    /// modified by the method is stored back through the containers holding it, up to its
+    /// variable or the first namespace.
+    fn call_method(&self, params: &CallParams<'_>, values: Context<'_>) -> Result<Option<Value>> {
+        let (method, receivers) = match self.subscript_expression.split_last() {
+            Some((method, receivers)) => (method, receivers),
+            None => return Ok(None),
+        };
+        let method = match method {
+            Subscript::Attribute(method) => method,
+            Subscript::Index(..) | Subscript::Slice(..) => return Ok(None),
+        };
+        // The containers the receiver was taken from, along with its subscript in each of them
+        let mut owners = vec![];
+        // Slices are copies, changes to them don't reach the variable
+        let mut copied = false;
+        let mut receiver = self.expression.evaluate(values.clone())?;
+        for subscript in receivers.iter() {
+            match subscript {
+                Subscript::Index(..) | Subscript::Attribute(..) => {
+                    let index = subscript.key(values.clone())?;
+                    owners.push((receiver.clone(), index.clone()));
+                    receiver = visitors::Subscription::apply(receiver, index)?;
+                }
+                Subscript::Slice(..) => {
+                    owners.clear();
+                    copied = true;
+                    receiver = subscript.apply(receiver, values.clone())?;
+                }
+            }
+        }
+        if !receiver.has_method(method) {
+            return Ok(None);
+        }
+        let args = params.evaluate(values.clone())?;
+        let modifying = receiver.is_modifying_method(method);
+        let result = receiver.call_method(method, args)?;
+        if !modifying {
+            return Ok(Some(result));
+        }
+        let mut item = receiver;
+        while let Some((mut owner, index)) = owners.pop() {
+            let shared = matches!(owner, Value::Namespace(_));
+            visitors::Subscription::assign(&mut owner, index, item)?;
+            if shared {
+                return Ok(Some(result));
+            }
+            item = owner;
+        }
+        if let (false, Expression::ValueRef(variable)) = (copied, &*self.expression) {
+            item.check_depth()?;
+            let mut values = values;
+            values.update(variable.identifier.clone(), item);
+        }
+        Ok(Some(result))
+    }
+}
+pub struct CallExpression<'a> {
+    expression: Box<Expression<'a>>,
+    params: CallParams<'a>,
+}
+impl<'a> CallExpression<'a> {
+    pub fn new(expression: Box<Expression<'a>>, params: CallParams<'a>) -> Self {
+        Self { expression, params }
+    }
+}
+impl CallExpression<'_> {
+    /// Performs the call, passing `caller` along with the arguments of the call site.
+    pub fn evaluate_with_caller(&self, values: Context<'_>, caller: Value) -> Result<Value> {
+        self.call(values, Some(caller))
+    }
+    fn call(&self, values: Context<'_>, caller: Option<Value>) -> Result<Value> {
+        if let (Expression::Subscript(subscript), None) = (&*self.expression, &caller) {
+            if let Some(result) = subscript.call_method(&self.params, values.clone())? {
+                return Ok(result);
+            }
+        }
+        match self.expression.evaluate(values.clone())? {
+            Value::Callable(callable) => {
+                let mut args = self.params.evaluate(values.clone())?;
+                if let Some(caller) = caller {
+                    args.keyword.insert("caller".to_string(), caller);
+                }
+                callable.call(args, &values)
+            }
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+}
+impl Evaluate for CallExpression<'_> {
+    fn evaluate(&self, values: Context<'_>) -> Result<Value> {
+        self.call(values, None)
+    }
+}
 impl Evaluate for Expression<'_> {
     fn evaluate(&self, values: Context<'_>) -> Result<Value> {
+        values.check_stack()?;
         let result = match &self {
             Expression::Constant(value) => value.clone(),
             Expression::Binary(op, left, right) => {
                 let left_val = left.evaluate(values.clone())?;
-                let right_val = right.evaluate(values)?;
-                visitors::BinaryMathOperation::apply(op, left_val, right_val)
+                let right_val = right.evaluate(values.clone())?;
+                match op {
+                    BinaryOperation::StringConcat => visitors::BinaryMathOperation::concat(
+                        left_val,
+                        right_val,
+                        values.autoescape(),
+                    )?,
+                    _ => visitors::BinaryMathOperation::apply(op, left_val, right_val)?,
+                }
             }
             Expression::Unary(op, expr) => {
                 let expression = expr.evaluate(values)?;
-                match op {
+                visitors::operation_result(match op {
                     UnaryOperation::Plus => expression,
                     UnaryOperation::Minus => -expression,
                     UnaryOperation::LogicalNot => !expression,
-                }
+                })?
             }
             Expression::Subscript(sub) => sub.evaluate(values)?,
             Expression::ValueRef(identifier) => identifier.evaluate(values)?,
             Expression::Tuple(tuple) => tuple.evaluate(values)?,
             Expression::Dict(dict) => dict.evaluate(values)?,
             Expression::Filtered(filter) => filter.evaluate(values)?,
+            Expression::Tested(test) => test.evaluate(values)?,
+            Expression::Call(call) => call.evaluate(values)?,
+            Expression::Conditional(condition, expression, else_expression) => {
+                match condition.evaluate(values.clone())? {
+                    Value::Boolean(true) => expression.evaluate(values)?,
+                    _ => match else_expression {
+                        Some(else_expression) => else_expression.evaluate(values)?,
+                        None => Value::Empty,
+                    },
+                }
+            }
         };
         Ok(result)
     }
@@ -179,9 +371,13 @@ pub struct FullExpressionEvaluator<'a> {
     expression: Option<Expression<'a>>,
 }
 
-impl Render for FullExpressionEvaluator<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
-        let value = self.evaluate(params)?;
+impl<'a> Render<'a> for FullExpressionEvaluator<'_> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        let autoescape = params.autoescape();
+        let mut value = self.evaluate(params)?;
+        if autoescape {
+            value = value.escape()?;
+        }
         if let Err(err) = out.write(value.to_string().as_bytes()) {
             Err(Error::Io(err))
         } else {
@@ -210,6 +406,10 @@ impl Evaluate for FullExpressionEvaluator<'_> {
 pub struct CallParams<'a> {
     pub kw_params: HashMap<String, FullExpressionEvaluator<'a>>,
     pub pos_params: Vec<FullExpressionEvaluator<'a>>,
+    /// List unpacked into positional parameters, as in `f(*args)`
+    pub var_params: Option<Box<FullExpressionEvaluator<'a>>>,
+    /// Map unpacked into keyword parameters, as in `f(**kwargs)`
+    pub var_kw_params: Option<Box<FullExpressionEvaluator<'a>>>,
 }
 
 impl CallParams<'_> {
@@ -218,18 +418,16 @@ impl CallParams<'_> {
         param_names: Vec<&'b str>,
         context: Context<'_>,
     ) -> Result<HashMap<&'b str, Value>> {
+        let mut args = self.evaluate(context)?;
+        let mut positional = args.positional.into_iter();
         let mut parameters = HashMap::default();
-        let mut idx = 0;
         for name in param_names {
-            match self.kw_params.get(name) {
-                Some(expression) => {
-                    let value = expression.evaluate(context.clone())?;
+            match args.keyword.remove(name) {
+                Some(value) => {
                     parameters.insert(name, value);
                 }
                 None => {
-                    if let Some(expression) = self.pos_params.get(idx) {
-                        idx += 1;
-                        let value = expression.evaluate(context.clone())?;
+                    if let Some(value) = positional.next() {
                         parameters.insert(name, value);
                     }
                 }
@@ -237,4 +435,35 @@ impl CallParams<'_> {
         }
         Ok(parameters)
     }
+    /// Evaluates every parameter, keeping them as they were given at the call site.
+    pub fn evaluate(&self, context: Context<'_>) -> Result<CallArgs> {
+        let mut args = CallArgs::default();
+        for expression in self.pos_params.iter() {
+            args.positional.push(expression.evaluate(context.clone())?);
+        }
+        if let Some(expression) = &self.var_params {
+            match expression.evaluate(context.clone())? {
+                Value::ValuesList(values) => args.positional.extend(values),
+                _ => return Err(Error::from(RenderErrorKind::InvalidValueType)),
+            }
+        }
+        for (name, expression) in self.kw_params.iter() {
+            args.keyword
+                .insert(name.to_string(), expression.evaluate(context.clone())?);
+        }
+        if let Some(expression) = &self.var_kw_params {
+            match expression.evaluate(context)? {
+                Value::ValuesMap(values) => {
+                    for (name, value) in values {
+                        if args.keyword.contains_key(&name) {
+                            return Err(Error::from(RenderErrorKind::MultipleValues(name)));
+                        }
+                        args.keyword.insert(name, value);
+                    }
+                }
+                _ => return Err(Error::from(RenderErrorKind::InvalidValueType)),
+            }
+        }
+        Ok(args)
+    }
 }
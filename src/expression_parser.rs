@@ -1,28 +1,39 @@
 use crate::error::{Error, ParseError, ParseErrorKind, Result};
 use crate::expression_evaluator::{
-    BinaryOperation, CallParams, DictionaryExpression, Expression, FilteredExpression,
-    FullExpressionEvaluator, SubscriptExpression, TupleExpression, UnaryOperation,
-    ValueRefExpression,
+    BinaryOperation, CallExpression, CallParams, DictionaryExpression, Evaluate, Expression,
+    FilteredExpression, FullExpressionEvaluator, Subscript, SubscriptExpression, TestedExpression,
+    TupleExpression, UnaryOperation, ValueRefExpression,
 };
 use crate::filters::FilterExpression;
 use crate::lexer::{PeekableLexer, Token};
 use crate::source::SourceLocationInfo;
+use crate::template_env::{Registry, Settings};
+use crate::testers::TesterExpression;
 use crate::value::Value;
 
 use crate::renderer::ExpressionRenderer;
 use logos::{Lexer, Logos};
+use std::sync::Arc;
 
 pub struct ExpressionParser {}
 
 impl ExpressionParser {
-    pub fn parse<'a>(text: &'a str) -> Result<ExpressionRenderer<'a>> {
-        let lexer: Lexer<'_, Token<'a>> = Token::lexer(text);
-        let mut lexer = PeekableLexer::new(lexer);
+    pub fn parse<'a>(
+        text: &'a str,
+        settings: &Settings,
+        registry: Arc<Registry>,
+    ) -> Result<ExpressionRenderer<'a>> {
+        let lexer: Lexer<'_, Token<'a>> = Token::lexer_with_extras(text, registry);
+        let mut lexer = PeekableLexer::new(lexer, settings.stack_budget);
 
         let evaluator = ExpressionParser::full_expresion_parser(&mut lexer)?;
 
-        if let Some(_tok) = lexer.next() {
-            todo!()
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
         }
         Ok(ExpressionRenderer::new(evaluator))
     }
@@ -32,7 +43,7 @@ impl ExpressionParser {
     ) -> Result<FullExpressionEvaluator<'a>> {
         let mut evaluator = FullExpressionEvaluator::default();
 
-        let value = ExpressionParser::parse_logical_or(lexer);
+        let value = ExpressionParser::parse_conditional(lexer);
         match value {
             Ok(expression) => evaluator.set_expression(expression),
             Err(err) => return Err(err),
@@ -41,11 +52,53 @@ impl ExpressionParser {
         Ok(evaluator)
     }
 
+    /// Parses an expression without a trailing `if` part, such as the iterable of a `for`
+    /// statement, whose `if` is the loop condition.
+    pub fn unconditional_expression_parser<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+    ) -> Result<FullExpressionEvaluator<'a>> {
+        let mut evaluator = FullExpressionEvaluator::default();
+        evaluator.set_expression(ExpressionParser::parse_logical_or(lexer)?);
+        Ok(evaluator)
+    }
+
+    /// Parses an expression which must end up calling something, as in `{% call %}`.
+    pub fn call_expression_parser<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+    ) -> Result<CallExpression<'a>> {
+        match ExpressionParser::parse_value_expression(lexer)? {
+            Expression::Call(call) => Ok(call),
+            _ => {
+                let range = lexer.span();
+                Err(Error::from(ParseError::new(
+                    ParseErrorKind::ExpectedBracket("("),
+                    Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                )))
+            }
+        }
+    }
+
+    fn parse_conditional<'a>(lexer: &mut PeekableLexer<'a, Token<'a>>) -> Result<Expression<'a>> {
+        let mut expression = ExpressionParser::parse_logical_or(lexer)?;
+        while let Some(Ok(Token::If)) = lexer.peek() {
+            lexer.next();
+            let condition = ExpressionParser::parse_logical_or(lexer)?;
+            let else_expression = if let Some(Ok(Token::Else)) = lexer.peek() {
+                lexer.next();
+                Some(Box::new(lexer.nested(ExpressionParser::parse_conditional)?))
+            } else {
+                None
+            };
+            expression =
+                Expression::Conditional(Box::new(condition), Box::new(expression), else_expression);
+        }
+        Ok(expression)
+    }
     fn parse_logical_or<'a>(lexer: &mut PeekableLexer<'a, Token<'a>>) -> Result<Expression<'a>> {
         let left = ExpressionParser::parse_logical_and(lexer)?;
         if let Some(Ok(Token::LogicalOr)) = lexer.peek() {
             lexer.next();
-            let right = ExpressionParser::parse_logical_or(lexer)?;
+            let right = lexer.nested(ExpressionParser::parse_logical_or)?;
             return Ok(Expression::Binary(
                 BinaryOperation::LogicalOr,
                 Box::new(left),
@@ -56,10 +109,10 @@ impl ExpressionParser {
     }
 
     fn parse_logical_and<'a>(lexer: &mut PeekableLexer<'a, Token<'a>>) -> Result<Expression<'a>> {
-        let left = ExpressionParser::parse_logical_compare(lexer)?;
+        let left = ExpressionParser::parse_logical_not(lexer)?;
         if let Some(Ok(Token::LogicalAnd)) = lexer.peek() {
             lexer.next();
-            let right = ExpressionParser::parse_logical_and(lexer)?;
+            let right = lexer.nested(ExpressionParser::parse_logical_and)?;
             return Ok(Expression::Binary(
                 BinaryOperation::LogicalAnd,
                 Box::new(left),
@@ -69,12 +122,32 @@ impl ExpressionParser {
         Ok(left)
     }
 
+    fn parse_logical_not<'a>(lexer: &mut PeekableLexer<'a, Token<'a>>) -> Result<Expression<'a>> {
+        if let Some(Ok(Token::LogicalNot)) = lexer.peek() {
+            lexer.next();
+            let expression = lexer.nested(ExpressionParser::parse_logical_not)?;
+            return Ok(Expression::Unary(
+                UnaryOperation::LogicalNot,
+                Box::new(expression),
+            ));
+        }
+        ExpressionParser::parse_logical_compare(lexer)
+    }
+
     fn parse_logical_compare<'a>(
         lexer: &mut PeekableLexer<'a, Token<'a>>,
     ) -> Result<Expression<'a>> {
         let left = ExpressionParser::parse_string_concat(lexer)?;
 
         let binary_op = match lexer.peek() {
+            Some(Ok(Token::In)) => BinaryOperation::In,
+            Some(Ok(Token::LogicalNot)) => match lexer.peek_second() {
+                Some(Ok(Token::In)) => {
+                    lexer.next();
+                    BinaryOperation::NotIn
+                }
+                _ => return Ok(left),
+            },
             Some(Ok(Token::Equal)) => BinaryOperation::LogicalEq,
             Some(Ok(Token::NotEqual)) => BinaryOperation::LogicalNe,
             Some(Ok(Token::Lt)) => BinaryOperation::LogicalLt,
@@ -98,7 +171,7 @@ impl ExpressionParser {
         let left = ExpressionParser::parse_math_pow(lexer)?;
         if let Some(Ok(Token::Tilde)) = lexer.peek() {
             lexer.next();
-            let right = ExpressionParser::parse_logical_and(lexer)?;
+            let right = lexer.nested(ExpressionParser::parse_logical_and)?;
             return Ok(Expression::Binary(
                 BinaryOperation::StringConcat,
                 Box::new(left),
@@ -112,7 +185,7 @@ impl ExpressionParser {
         let left = ExpressionParser::parse_math_plus_minus(lexer)?;
         if let Some(Ok(Token::MulMul)) = lexer.peek() {
             lexer.next();
-            let right = ExpressionParser::parse_math_pow(lexer)?;
+            let right = lexer.nested(ExpressionParser::parse_math_pow)?;
             return Ok(Expression::Binary(
                 BinaryOperation::Pow,
                 Box::new(left),
@@ -132,7 +205,7 @@ impl ExpressionParser {
             _ => return Ok(left),
         };
         lexer.next();
-        let right = ExpressionParser::parse_math_plus_minus(lexer)?;
+        let right = lexer.nested(ExpressionParser::parse_math_plus_minus)?;
         Ok(Expression::Binary(
             binary_op,
             Box::new(left),
@@ -150,7 +223,7 @@ impl ExpressionParser {
             _ => return Ok(left),
         };
         lexer.next();
-        let right = ExpressionParser::parse_math_mul_div(lexer)?;
+        let right = lexer.nested(ExpressionParser::parse_math_mul_div)?;
 
         Ok(Expression::Binary(
             binary_op,
@@ -165,47 +238,100 @@ impl ExpressionParser {
         let unary_op = match lexer.peek() {
             Some(Ok(Token::Plus)) => Some(UnaryOperation::Plus),
             Some(Ok(Token::Minus)) => Some(UnaryOperation::Minus),
-            Some(Ok(Token::LogicalNot)) => Some(UnaryOperation::LogicalNot),
             _ => None,
         };
         if unary_op.is_some() {
             lexer.next();
         }
 
-        let sub_expr = ExpressionParser::parse_value_expression(lexer)?;
-
-        let result = match unary_op {
-            Some(op) => Expression::Unary(op, Box::new(sub_expr)),
-            None => sub_expr,
-        };
+        let mut sub_expr = ExpressionParser::parse_value_expression(lexer)?;
 
         if let Some(Ok(Token::Pipe)) = lexer.peek() {
             lexer.next();
             let filter_expression = ExpressionParser::parse_filter_expression(lexer)?;
-            Ok(Expression::Filtered(FilteredExpression::new(
-                Box::new(result),
+            sub_expr = Expression::Filtered(FilteredExpression::new(
+                Box::new(sub_expr),
                 filter_expression,
-            )))
-        } else {
-            Ok(result)
+            ));
         }
+        if let Some(Ok(Token::Is)) = lexer.peek() {
+            lexer.next();
+            let tester_expression = ExpressionParser::parse_tester_expression(lexer)?;
+            sub_expr =
+                Expression::Tested(TestedExpression::new(Box::new(sub_expr), tester_expression));
+        }
+
+        Ok(match unary_op {
+            Some(op) => Expression::Unary(op, Box::new(sub_expr)),
+            None => sub_expr,
+        })
+    }
+    /// Parses the test following `is`, such as `not divisibleby(3)` or `sameas false`.
+    fn parse_tester_expression<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+    ) -> Result<TesterExpression<'a>> {
+        let negated = if let Some(Ok(Token::LogicalNot)) = lexer.peek() {
+            lexer.next();
+            true
+        } else {
+            false
+        };
+        let identifier = match lexer.next() {
+            Some(Ok(Token::Identifier(identifier))) => identifier.to_string(),
+            Some(Ok(Token::None)) => "none".to_string(),
+            Some(Ok(Token::In)) => "in".to_string(),
+            _ => {
+                let range = lexer.span();
+                return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                    SourceLocationInfo::new_with_range(range.start, range.end),
+                )));
+            }
+        };
+        let params = match lexer.peek() {
+            Some(Ok(Token::LBracket)) => {
+                lexer.next();
+                lexer.nested(ExpressionParser::parse_call_params)?
+            }
+            // A single argument may be given without brackets
+            Some(Ok(Token::IntegerNum(_)))
+            | Some(Ok(Token::FloatNum(_)))
+            | Some(Ok(Token::String(_)))
+            | Some(Ok(Token::Identifier(_)))
+            | Some(Ok(Token::True))
+            | Some(Ok(Token::False))
+            | Some(Ok(Token::None))
+            | Some(Ok(Token::LSqBracket))
+            | Some(Ok(Token::LCrlBracket)) => {
+                let mut argument = FullExpressionEvaluator::default();
+                argument.set_expression(ExpressionParser::parse_value_expression(lexer)?);
+                let mut params = CallParams::default();
+                params.pos_params.push(argument);
+                Some(params)
+            }
+            _ => None,
+        };
+        TesterExpression::new(&identifier, params, negated, lexer.extras())
     }
-    fn parse_filter_expression<'a>(
+    pub fn parse_filter_expression<'a>(
         lexer: &mut PeekableLexer<'a, Token<'a>>,
     ) -> Result<FilterExpression<'a>> {
         let mut result: Option<FilterExpression<'_>> = None;
+        let mut chained = 0;
         loop {
+            chained += 1;
+            lexer.check_depth(chained)?;
             match lexer.next() {
                 Some(token) => {
                     if let Ok(Token::Identifier(identifier)) = token {
                         let params = if let Some(Ok(Token::LBracket)) = lexer.peek() {
                             lexer.next();
-                            ExpressionParser::parse_call_params(lexer)?
+                            lexer.nested(ExpressionParser::parse_call_params)?
                         } else {
                             None
                         };
 
-                        let mut filter = FilterExpression::new(&identifier, params)?;
+                        let mut filter =
+                            FilterExpression::new(&identifier, params, lexer.extras())?;
                         if let Some(expression) = result.take() {
                             filter.set_parent_filter(expression);
                         }
@@ -230,7 +356,13 @@ impl ExpressionParser {
                 }
             }
         }
-        Ok(result.unwrap())
+        result.ok_or_else(|| {
+            let range = lexer.span();
+            Error::from(ParseErrorKind::ExpectedIdentifier(SourceLocationInfo::new(
+                range.start,
+                range.end,
+            )))
+        })
     }
     fn parse_call_params<'a>(
         lexer: &mut PeekableLexer<'a, Token<'a>>,
@@ -242,11 +374,34 @@ impl ExpressionParser {
         }
 
         loop {
-            let mut params_name: Option<String> = None;
-            if let Some(Ok(Token::Identifier(keyword))) = lexer.peek() {
-                params_name = Some(keyword.to_string());
+            if let Some(Ok(token @ (Token::Mul | Token::MulMul))) = lexer.peek() {
+                let unpack_map = matches!(token, Token::MulMul);
                 lexer.next();
-                if let Some(Ok(Token::Assign)) = lexer.peek() {
+                let slot = if unpack_map {
+                    &mut params.var_kw_params
+                } else {
+                    &mut params.var_params
+                };
+                if slot.is_some() {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseError::new(
+                        ParseErrorKind::UnexpectedToken,
+                        Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                    )));
+                }
+                *slot = Some(Box::new(ExpressionParser::full_expresion_parser(lexer)?));
+                if let Some(Ok(Token::Comma)) = lexer.peek() {
+                    lexer.next();
+                    continue;
+                } else {
+                    break;
+                }
+            }
+            let mut params_name: Option<String> = None;
+            if let Some(Ok(Token::Assign)) = lexer.peek_second() {
+                if let Some(Ok(Token::Identifier(keyword))) = lexer.peek() {
+                    params_name = Some(keyword.to_string());
+                    lexer.next();
                     lexer.next();
                 }
             }
@@ -282,14 +437,17 @@ impl ExpressionParser {
                 Ok(Token::IntegerNum(num)) => Expression::Constant(Value::from(num)),
                 Ok(Token::True) => Expression::Constant(Value::from(true)),
                 Ok(Token::False) => Expression::Constant(Value::from(false)),
+                Ok(Token::None) => Expression::Constant(Value::None),
                 Ok(Token::FloatNum(num)) => Expression::Constant(Value::from(num)),
                 Ok(Token::String(string)) => Expression::Constant(Value::from(string.to_string())),
-                Ok(Token::LBracket) => ExpressionParser::parse_braced_expression_or_tuple(lexer)?,
+                Ok(Token::LBracket) => {
+                    lexer.nested(ExpressionParser::parse_braced_expression_or_tuple)?
+                }
                 Ok(Token::Identifier(identifier)) => {
                     Expression::ValueRef(ValueRefExpression::new(identifier.to_string()))
                 }
-                Ok(Token::LSqBracket) => ExpressionParser::parse_tuple(lexer)?,
-                Ok(Token::LCrlBracket) => ExpressionParser::parse_dict(lexer)?,
+                Ok(Token::LSqBracket) => lexer.nested(ExpressionParser::parse_tuple)?,
+                Ok(Token::LCrlBracket) => lexer.nested(ExpressionParser::parse_dict)?,
 
                 _ => {
                     let range = lexer.span();
@@ -307,15 +465,26 @@ impl ExpressionParser {
             )));
         };
 
-        let token = lexer.peek();
-
-        let value = match token {
-            Some(Ok(Token::LSqBracket)) | Some(Ok(Token::Point)) => {
-                ExpressionParser::parse_subscript(lexer, value)?
-            }
-            Some(Ok(Token::LBracket)) => todo!(),
-            _ => value,
-        };
+        let mut value = value;
+        // Each call and subscription wraps the expression into one more level.
+        let mut chained = 0;
+        loop {
+            chained += 1;
+            lexer.check_depth(chained)?;
+            value = match lexer.peek() {
+                Some(Ok(Token::LSqBracket)) | Some(Ok(Token::Point)) => {
+                    lexer.nested(|lexer| ExpressionParser::parse_subscript(lexer, value))?
+                }
+                Some(Ok(Token::LBracket)) => {
+                    lexer.next();
+                    let params = lexer
+                        .nested(ExpressionParser::parse_call_params)?
+                        .unwrap_or_default();
+                    Expression::Call(CallExpression::new(Box::new(value), params))
+                }
+                _ => break,
+            };
+        }
 
         Ok(value)
     }
@@ -330,7 +499,7 @@ impl ExpressionParser {
                 lexer.next();
                 break;
             }
-            let expr = ExpressionParser::parse_logical_or(lexer);
+            let expr = ExpressionParser::parse_conditional(lexer);
             match expr {
                 Ok(expr) => exprs.push(expr),
                 Err(err) => {
@@ -349,7 +518,8 @@ impl ExpressionParser {
                 is_tuple = true;
             }
         }
-        if is_tuple {
+        // `()` is the empty tuple.
+        if is_tuple || exprs.is_empty() {
             let mut tuple = TupleExpression::default();
             for expr in exprs {
                 tuple.push(Box::new(expr));
@@ -368,9 +538,9 @@ impl ExpressionParser {
             match token {
                 Ok(Token::LSqBracket) => {
                     lexer.next();
-                    let expr = ExpressionParser::full_expresion_parser(lexer)?;
+                    let item = ExpressionParser::parse_subscript_item(lexer)?;
                     if let Some(Ok(Token::RSqBracket)) = lexer.next() {
-                        subscript.add_index(Box::new(expr));
+                        subscript.add_index(item);
                     } else {
                         let range = lexer.span();
 
@@ -384,9 +554,7 @@ impl ExpressionParser {
                     lexer.next();
                     let token = lexer.next();
                     if let Some(Ok(Token::Identifier(identifier))) = token {
-                        subscript.add_index(Box::new(Expression::Constant(Value::String(
-                            identifier.to_string(),
-                        ))));
+                        subscript.add_index(Subscript::Attribute(identifier.to_string()));
                     } else {
                         let range = lexer.span();
                         return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
@@ -400,6 +568,38 @@ impl ExpressionParser {
 
         Ok(Expression::Subscript(subscript))
     }
+    /// Parses what goes between the brackets of a subscript: an index or a slice.
+    fn parse_subscript_item<'a>(lexer: &mut PeekableLexer<'a, Token<'a>>) -> Result<Subscript<'a>> {
+        let start: Option<Box<dyn Evaluate + 'a>> = match lexer.peek() {
+            Some(Ok(Token::Colon)) => None,
+            _ => {
+                let start = ExpressionParser::full_expresion_parser(lexer)?;
+                if !matches!(lexer.peek(), Some(Ok(Token::Colon))) {
+                    return Ok(Subscript::Index(Box::new(start)));
+                }
+                Some(Box::new(start))
+            }
+        };
+        lexer.next();
+        let stop = ExpressionParser::parse_slice_bound(lexer)?;
+        let step = if let Some(Ok(Token::Colon)) = lexer.peek() {
+            lexer.next();
+            ExpressionParser::parse_slice_bound(lexer)?
+        } else {
+            None
+        };
+        Ok(Subscript::Slice(start, stop, step))
+    }
+    fn parse_slice_bound<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+    ) -> Result<Option<Box<dyn Evaluate + 'a>>> {
+        match lexer.peek() {
+            Some(Ok(Token::Colon)) | Some(Ok(Token::RSqBracket)) => Ok(None),
+            _ => Ok(Some(Box::new(ExpressionParser::full_expresion_parser(
+                lexer,
+            )?))),
+        }
+    }
     fn parse_tuple<'a>(lexer: &mut PeekableLexer<'a, Token<'a>>) -> Result<Expression<'a>> {
         let mut tuple = TupleExpression::default();
         if let Some(Ok(Token::RSqBracket)) = lexer.peek() {
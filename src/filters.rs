@@ -1,8 +1,12 @@
 use crate::context::Context;
 use crate::error::{Error, ParseError, ParseErrorKind, Result};
 use crate::expression_evaluator::CallParams;
-use crate::value::Value;
+use crate::template_env::Registry;
+use crate::value::{CallArgs, Value};
 use std::collections::HashMap;
+use std::sync::Arc;
+
+pub type FilterFn = dyn Fn(Value, CallArgs, &Context<'_>) -> Result<Value> + Send + Sync;
 
 pub enum Filter {
     Abs,
@@ -12,6 +16,7 @@ pub enum Filter {
     Escape,
     First,
     Float,
+    ForceEscape,
     Int,
     Last,
     Length,
@@ -19,15 +24,20 @@ pub enum Filter {
     Max,
     Min,
     Round,
+    Safe,
     String,
     Sum,
     Title,
     Truncate,
     Upper,
     WordCount,
+    Custom(Arc<FilterFn>),
 }
 impl Filter {
-    pub fn new(name: &str) -> Result<Self> {
+    pub fn new(name: &str, registry: &Registry) -> Result<Self> {
+        if let Some(filter) = registry.filters.get(name) {
+            return Ok(Filter::Custom(filter.clone()));
+        }
         match name {
             "abs" => Ok(Filter::Abs),
             "capitalize" => Ok(Filter::Capitalize),
@@ -36,6 +46,7 @@ impl Filter {
             "escape" | "e" => Ok(Filter::Escape),
             "first" => Ok(Filter::First),
             "float" => Ok(Filter::Float),
+            "forceescape" => Ok(Filter::ForceEscape),
             "int" => Ok(Filter::Int),
             "last" => Ok(Filter::Last),
             "length" | "count" => Ok(Filter::Length),
@@ -43,6 +54,7 @@ impl Filter {
             "max" => Ok(Filter::Max),
             "min" => Ok(Filter::Min),
             "round" => Ok(Filter::Round),
+            "safe" => Ok(Filter::Safe),
             "string" => Ok(Filter::String),
             "sum" => Ok(Filter::Sum),
             "title" => Ok(Filter::Title),
@@ -60,6 +72,35 @@ impl Filter {
         base_value: Value,
         params: &Option<CallParams<'_>>,
         context: Context<'_>,
+    ) -> Result<Value> {
+        let is_safe = matches!(base_value, Value::SafeString(_));
+        let base_value = match (self, base_value) {
+            (
+                Filter::Default
+                | Filter::Escape
+                | Filter::ForceEscape
+                | Filter::Safe
+                | Filter::Custom(_),
+                value,
+            ) => value,
+            (_, Value::SafeString(s)) => Value::String(s),
+            (_, value) => value,
+        };
+        let result = self.apply(base_value, params, context)?;
+        // Filters which leave the markup as it is keep the safe mark, the others drop it
+        match (self, result) {
+            (
+                Filter::Capitalize | Filter::Lower | Filter::String | Filter::Title | Filter::Upper,
+                Value::String(s),
+            ) if is_safe => Ok(Value::SafeString(s)),
+            (_, result) => Ok(result),
+        }
+    }
+    fn apply(
+        &self,
+        base_value: Value,
+        params: &Option<CallParams<'_>>,
+        context: Context<'_>,
     ) -> Result<Value> {
         match &self {
             Filter::Abs => base_value.abs(),
@@ -87,6 +128,7 @@ impl Filter {
             }
             Filter::Escape => base_value.escape(),
             Filter::First => base_value.first(),
+            Filter::ForceEscape => base_value.force_escape(),
             Filter::Int => {
                 let parameters = if params.is_some() {
                     params
@@ -123,6 +165,7 @@ impl Filter {
                 };
                 base_value.round(parameters)
             }
+            Filter::Safe => base_value.safe(),
             Filter::String => Ok(Value::String(base_value.to_string())),
             Filter::Sum => base_value.sum(), // TODO: ACcept params
             Filter::Title => base_value.title(),
@@ -140,6 +183,13 @@ impl Filter {
             } // TODO: Add additional parameters.,
             Filter::Upper => base_value.upper(),
             Filter::WordCount => base_value.wordcount(),
+            Filter::Custom(filter) => {
+                let args = match params {
+                    Some(params) => params.evaluate(context.clone())?,
+                    None => CallArgs::default(),
+                };
+                filter(base_value, args, &context)
+            }
         }
     }
 }
@@ -151,8 +201,12 @@ pub struct FilterExpression<'a> {
 }
 
 impl<'a> FilterExpression<'a> {
-    pub fn new(identifier: &str, params: Option<CallParams<'a>>) -> Result<Self> {
-        let filter = Filter::new(identifier)?;
+    pub fn new(
+        identifier: &str,
+        params: Option<CallParams<'a>>,
+        registry: &Registry,
+    ) -> Result<Self> {
+        let filter = Filter::new(identifier, registry)?;
         Ok(Self {
             filter,
             params,
@@ -164,12 +218,9 @@ impl<'a> FilterExpression<'a> {
     }
 
     pub fn filter(&self, base_value: Value, context: Context<'_>) -> Result<Value> {
-        if self.parent.is_some() {
+        if let Some(parent) = &self.parent {
             self.filter.filter(
-                self.parent
-                    .as_ref()
-                    .unwrap()
-                    .filter(base_value, context.clone())?,
+                parent.filter(base_value, context.clone())?,
                 &self.params,
                 context,
             )
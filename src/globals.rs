@@ -0,0 +1,220 @@
+use crate::context::Context;
+use crate::error::{Error, RenderErrorKind, Result};
+use crate::value::{check_length, CallArgs, Callable, Namespace, Value, ValuesMap};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const LOREM_IPSUM_WORDS: &str = "\
+    a ac accumsan ad adipiscing aenean aliquam aliquet amet ante aptent arcu at auctor augue \
+    bibendum blandit class commodo condimentum congue consectetuer consequat conubia convallis \
+    cras cubilia curabitur curae cursus dapibus diam dictum dictumst dignissim dis dolor donec \
+    dui duis egestas eget eleifend elementum elit enim erat eros est et etiam eu euismod \
+    facilisi facilisis fames faucibus felis fermentum feugiat fringilla fusce gravida habitant \
+    habitasse hac hendrerit hymenaeos iaculis id imperdiet in inceptos integer interdum ipsum \
+    justo lacinia lacus laoreet lectus leo libero ligula litora lobortis lorem luctus maecenas \
+    magna magnis malesuada massa mattis mauris metus mi molestie mollis montes morbi mus nam \
+    nascetur natoque nec neque netus nibh nisi nisl non nonummy nostra nulla nullam nunc odio \
+    orci ornare parturient pede pellentesque penatibus per pharetra phasellus placerat platea \
+    porta porttitor posuere potenti praesent pretium primis proin pulvinar purus quam quis \
+    quisque rhoncus ridiculus risus rutrum sagittis sapien scelerisque sed sem semper senectus \
+    sit sociis sociosqu sodales sollicitudin suscipit suspendisse taciti tellus tempor tempus \
+    tincidunt torquent tortor tristique turpis ullamcorper ultrices ultricies urna ut varius \
+    vehicula vel velit venenatis vestibulum vitae vivamus viverra volutpat vulputate";
+
+/// The functions every environment provides as globals.
+pub(crate) fn default_globals() -> ValuesMap {
+    let mut globals = ValuesMap::default();
+    globals.insert("range".to_string(), Callable::new("range", range).into());
+    globals.insert("dict".to_string(), Callable::new("dict", dict).into());
+    globals.insert("lipsum".to_string(), Callable::new("lipsum", lipsum).into());
+    globals.insert("cycler".to_string(), Callable::new("cycler", cycler).into());
+    globals.insert("joiner".to_string(), Callable::new("joiner", joiner).into());
+    globals.insert(
+        "namespace".to_string(),
+        Callable::new("namespace", namespace).into(),
+    );
+    globals
+}
+
+/// `range([start,] stop[, step])`, the list of integers going from start to stop.
+fn range(args: CallArgs, _context: &Context<'_>) -> Result<Value> {
+    let (start, stop) = match args.get_optional::<i64>(1, "stop")? {
+        Some(stop) => (args.get(0, "start")?, stop),
+        None => (0, args.get(0, "stop")?),
+    };
+    let step: i64 = args.get_optional(2, "step")?.unwrap_or(1);
+    if step == 0 {
+        return Err(Error::from(RenderErrorKind::InvalidOperation));
+    }
+    let mut values = vec![];
+    let mut current = start;
+    while (step > 0 && current < stop) || (step < 0 && current > stop) {
+        check_length(values.len() + 1)?;
+        values.push(Value::Integer(current));
+        current = match current.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    Ok(Value::ValuesList(values))
+}
+
+/// `dict(**kw)`, a mapping made of the keyword arguments.
+fn dict(args: CallArgs, _context: &Context<'_>) -> Result<Value> {
+    Ok(Value::ValuesMap(args.keyword))
+}
+
+/// Generator used by `lipsum`, seeded the same way on every call so the output is stable.
+struct LipsumRandom(u64);
+
+impl LipsumRandom {
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        if high <= low {
+            low
+        } else {
+            let span = (high as i128 - low as i128) as u128;
+            (low as i128 + ((self.0 >> 33) as u128 % span) as i128) as i64
+        }
+    }
+}
+
+/// `lipsum(n=5, html=True, min=20, max=100)`, paragraphs of lorem ipsum text. Unlike in Jinja2,
+/// the text only depends on the arguments, so that renders stay reproducible, for instance to
+/// compare them in tests or to cache them.
+fn lipsum(args: CallArgs, _context: &Context<'_>) -> Result<Value> {
+    let paragraphs: i64 = args.get_optional(0, "n")?.unwrap_or(5);
+    let html: bool = args.get_optional(1, "html")?.unwrap_or(true);
+    let min: i64 = args.get_optional(2, "min")?.unwrap_or(20);
+    let max: i64 = args.get_optional(3, "max")?.unwrap_or(100);
+    if min > max {
+        return Err(Error::from(RenderErrorKind::InvalidOperation));
+    }
+
+    check_length(usize::try_from(paragraphs).unwrap_or(0))?;
+
+    let dictionary: Vec<&str> = LOREM_IPSUM_WORDS.split_whitespace().collect();
+    let mut random = LipsumRandom(paragraphs as u64);
+    let mut result = vec![];
+    let mut total_words = 0;
+    for _ in 0..paragraphs {
+        let mut next_capitalized = true;
+        let mut last_comma = 0;
+        let mut last_fullstop = 0;
+        let mut last_word = "";
+        let mut words: Vec<String> = vec![];
+        let count = random.range(min, max);
+        total_words += usize::try_from(count).unwrap_or(0);
+        check_length(total_words)?;
+        for idx in 0..count {
+            let mut word = last_word;
+            while word == last_word {
+                word = dictionary[random.range(0, dictionary.len() as i64) as usize];
+            }
+            last_word = word;
+            let mut word = word.to_string();
+            if next_capitalized {
+                word = word[..1].to_uppercase() + &word[1..];
+                next_capitalized = false;
+            }
+            if idx - random.range(3, 8) > last_comma {
+                last_comma = idx;
+                last_fullstop += 2;
+                word.push(',');
+            }
+            if idx - random.range(10, 20) > last_fullstop {
+                last_comma = idx;
+                last_fullstop = idx;
+                word.push('.');
+                next_capitalized = true;
+            }
+            words.push(word);
+        }
+        let mut paragraph = words.join(" ");
+        if paragraph.ends_with(',') {
+            paragraph.pop();
+        }
+        if !paragraph.ends_with('.') {
+            paragraph.push('.');
+        }
+        result.push(paragraph);
+    }
+    if html {
+        let paragraphs: Vec<String> = result
+            .into_iter()
+            .map(|paragraph| format!("<p>{}</p>", paragraph))
+            .collect();
+        Ok(Value::SafeString(paragraphs.join("\n")))
+    } else {
+        Ok(Value::String(result.join("\n\n")))
+    }
+}
+
+/// `cycler(*items)`, going through the items with `next()` and back to the first with `reset()`.
+fn cycler(args: CallArgs, _context: &Context<'_>) -> Result<Value> {
+    let items = Arc::new(args.positional);
+    let first = items
+        .first()
+        .cloned()
+        .ok_or_else(|| Error::from(RenderErrorKind::InvalidOperation))?;
+    let cycler = Namespace::new(ValuesMap::default());
+    let position = Arc::new(Mutex::new(0));
+    // The methods are stored in the cycler itself, so they only keep a weak reference to it.
+    let attributes = Arc::downgrade(&cycler.0);
+
+    let (next_items, next_position, next_attributes) =
+        (items.clone(), position.clone(), attributes.clone());
+    let next = Callable::new("next", move |_args, _context| {
+        let mut position = next_position.lock().unwrap();
+        let item = next_items[*position].clone();
+        *position = (*position + 1) % next_items.len();
+        if let Some(attributes) = next_attributes.upgrade() {
+            Namespace(attributes).set("current".to_string(), next_items[*position].clone());
+        }
+        Ok(item)
+    });
+    let reset = Callable::new("reset", move |_args, _context| {
+        *position.lock().unwrap() = 0;
+        if let Some(attributes) = attributes.upgrade() {
+            Namespace(attributes).set("current".to_string(), items[0].clone());
+        }
+        Ok(Value::None)
+    });
+    cycler.set("current".to_string(), first);
+    cycler.set("next".to_string(), next.into());
+    cycler.set("reset".to_string(), reset.into());
+    Ok(Value::Namespace(cycler))
+}
+
+/// `joiner(sep=", ")`, returning an empty string on the first call and the separator after.
+fn joiner(args: CallArgs, _context: &Context<'_>) -> Result<Value> {
+    let separator: String = args
+        .get_optional(0, "sep")?
+        .unwrap_or_else(|| ", ".to_string());
+    let used = AtomicBool::new(false);
+    Ok(Value::Callable(Callable::new(
+        "joiner",
+        move |_args, _context| {
+            if used.swap(true, Ordering::SeqCst) {
+                Ok(Value::String(separator.clone()))
+            } else {
+                Ok(Value::String(String::new()))
+            }
+        },
+    )))
+}
+
+/// `namespace([attributes,] **kw)`, an object whose attributes can be assigned with `{% set ns.attr = ... %}`.
+fn namespace(args: CallArgs, _context: &Context<'_>) -> Result<Value> {
+    let mut attributes = match args.positional.into_iter().next() {
+        Some(Value::ValuesMap(attributes)) => attributes,
+        Some(_) => return Err(Error::from(RenderErrorKind::InvalidValueType)),
+        None => ValuesMap::default(),
+    };
+    attributes.extend(args.keyword);
+    Ok(Value::Namespace(Namespace::new(attributes)))
+}
@@ -25,5 +25,11 @@ RegexEnum {
     CommentBegin => r"\{#",
     CommentEnd => r"#\}",
     NewLine => r"\n",
+    LineCommentBegin => r"(?:(?m:^)[ \t]*)?<line_comment_prefix>",
+    LineStmtBegin => r"(?m:^)[ \t]*<line_statement_prefix>",
 }, ROUGH_TOKENIZER
 );
+
+/// Placeholders of `ROUGH_TOKENIZER` replaced by the prefixes set in the template settings.
+pub const LINE_COMMENT_PREFIX: &str = "<line_comment_prefix>";
+pub const LINE_STATEMENT_PREFIX: &str = "<line_statement_prefix>";
@@ -1,7 +1,12 @@
+use crate::error::{Error, ParseErrorKind, Result};
+use crate::source::SourceLocationInfo;
+use crate::stack;
+use crate::template_env::Registry;
 use logos::{Lexer, Logos};
 use std::{
     borrow::Cow,
     num::{ParseFloatError, ParseIntError},
+    sync::Arc,
 };
 
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
@@ -22,6 +27,7 @@ impl From<ParseFloatError> for ParseError {
 #[derive(Debug, Clone, PartialEq, Logos)]
 #[logos(
     error = ParseError,
+    extras = Arc<Registry>,
 )]
 #[logos(skip r"[ \t\n\f]+")]
 pub enum Token<'a> {
@@ -171,6 +177,14 @@ pub enum Token<'a> {
     As,
     #[token("do")]
     Do,
+    #[token("autoescape")]
+    Autoescape,
+    #[token("endautoescape")]
+    EndAutoescape,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
 
     // Template control
     #[token("{#")]
@@ -191,38 +205,88 @@ pub enum Token<'a> {
     ExprEnd,
 }
 
+/// How deeply expressions and statements may nest, so that malformed templates cannot
+/// overflow the stack.
+pub const MAX_NESTING_DEPTH: usize = 128;
+
 pub struct PeekableLexer<'source, T: Logos<'source>> {
     lexer: Lexer<'source, T>,
-    peeked: Option<Option<Result<T, T::Error>>>,
+    peeked: Option<Option<std::result::Result<T, T::Error>>>,
+    depth: usize,
+    stack_base: usize,
+    stack_budget: usize,
 }
 
 impl<'source, T> PeekableLexer<'source, T>
 where
     T: Logos<'source>,
 {
-    pub fn new(lexer: Lexer<'source, T>) -> Self {
+    /// Wraps `lexer`, allowing parsing to use `stack_budget` bytes of stack.
+    pub fn new(lexer: Lexer<'source, T>, stack_budget: usize) -> Self {
         Self {
             lexer,
             peeked: None,
+            depth: 0,
+            stack_base: stack::position(),
+            stack_budget,
         }
     }
     #[inline]
-    pub fn peek(&mut self) -> Option<&Result<T, T::Error>> {
+    pub fn peek(&mut self) -> Option<&std::result::Result<T, T::Error>> {
         let lexer = &mut self.lexer;
         self.peeked.get_or_insert_with(|| lexer.next()).as_ref()
     }
 
+    /// Returns the token following the peeked one, without consuming any of them.
+    pub fn peek_second(&mut self) -> Option<std::result::Result<T, T::Error>>
+    where
+        T: Clone,
+        T::Extras: Clone,
+    {
+        self.peek();
+        self.lexer.clone().next()
+    }
+
+    /// Returns the extras the lexer was built with.
+    pub fn extras(&self) -> &T::Extras {
+        &self.lexer.extras
+    }
+
     #[inline]
     pub fn span(&self) -> core::ops::Range<usize> {
         self.lexer.span()
     }
+
+    /// Fails if nesting `levels` more expressions would exceed `MAX_NESTING_DEPTH`, or if
+    /// parsing used up its stack budget.
+    pub fn check_depth(&self, levels: usize) -> Result<()> {
+        if self.depth + levels > MAX_NESTING_DEPTH
+            || stack::exhausted(self.stack_base, self.stack_budget)
+        {
+            let range = self.span();
+            return Err(Error::from(crate::error::ParseError::new(
+                ParseErrorKind::NestingTooDeep,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Parses a nested expression with `parse`, one level deeper.
+    pub fn nested<R>(&mut self, parse: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        self.check_depth(1)?;
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
 }
 
 impl<'source, T> Iterator for PeekableLexer<'source, T>
 where
     T: Logos<'source>,
 {
-    type Item = Result<T, T::Error>;
+    type Item = std::result::Result<T, T::Error>;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
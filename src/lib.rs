@@ -6,19 +6,22 @@ mod expression_evaluator;
 mod expression_parser;
 mod filesystem_handler;
 mod filters;
+mod globals;
 mod keyword;
 mod lexer;
 mod renderer;
 mod source;
+mod stack;
 mod statement;
 mod template;
 mod template_env;
 mod template_parser;
+mod testers;
 
 pub use context::Context;
 pub use filesystem_handler::{FileSystemHandler, MemoryFileSystem, RealFileSystem};
 pub use template::Template;
-pub use template_env::TemplateEnv;
+pub use template_env::{Extensions, Settings, TemplateEnv, UndefinedBehavior};
 
 #[macro_use]
 extern crate lazy_static;
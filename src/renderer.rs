@@ -7,11 +7,11 @@ use std::io::Write;
 use std::sync::RwLock;
 
 pub struct ComposedRenderer<'a> {
-    renderers: RwLock<Vec<Box<dyn Render + 'a>>>,
+    renderers: RwLock<Vec<Box<dyn Render<'a> + 'a>>>,
 }
 
-pub trait Render {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()>;
+pub trait Render<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()>;
 }
 
 impl<'a> ComposedRenderer<'a> {
@@ -19,7 +19,7 @@ impl<'a> ComposedRenderer<'a> {
         let renderers = RwLock::new(vec![]);
         Self { renderers }
     }
-    pub fn add_renderer(&self, renderer: Box<dyn Render + 'a>) {
+    pub fn add_renderer(&self, renderer: Box<dyn Render<'a> + 'a>) {
         self.renderers.write().unwrap().push(renderer)
     }
 }
@@ -30,10 +30,14 @@ impl Default for ComposedRenderer<'_> {
     }
 }
 
-impl Render for ComposedRenderer<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
+impl<'a> Render<'a> for ComposedRenderer<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        params.check_stack()?;
         for r in self.renderers.read().unwrap().iter() {
             r.render(out, params.clone())?;
+            if params.loop_control().is_some() {
+                break;
+            }
         }
         Ok(())
     }
@@ -61,8 +65,8 @@ impl<'a> RawTextRenderer<'a> {
     }
 }
 
-impl Render for RawTextRenderer<'_> {
-    fn render(&self, out: &mut dyn Write, _params: Context<'_>) -> Result<()> {
+impl<'a> Render<'a> for RawTextRenderer<'_> {
+    fn render(&self, out: &mut dyn Write, _params: Context<'a>) -> Result<()> {
         if let Err(err) = out.write(self.content.as_bytes()) {
             Err(Error::Io(err))
         } else {
@@ -75,8 +79,8 @@ pub struct ExpressionRenderer<'a> {
     expression: FullExpressionEvaluator<'a>,
 }
 
-impl Render for ExpressionRenderer<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
+impl<'a> Render<'a> for ExpressionRenderer<'_> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
         self.expression.render(out, params)
     }
 }
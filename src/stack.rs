@@ -0,0 +1,18 @@
+//! Guards against stack overflows while parsing and rendering templates which nest or
+//! recurse deeply.
+
+/// Stack space that parsing or rendering a template may use by default, with room left on a
+/// 2 MiB thread stack for the host and for loading templates while rendering.
+pub(crate) const DEFAULT_STACK_BUDGET: usize = 768 * 1024;
+
+/// Returns the current depth of the stack, as the address of a local variable.
+#[inline(always)]
+pub(crate) fn position() -> usize {
+    let marker = 0_u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Tells whether more than `budget` bytes of stack were used since `base` was taken.
+pub(crate) fn exhausted(base: usize, budget: usize) -> bool {
+    base.abs_diff(position()) > budget
+}
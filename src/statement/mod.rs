@@ -1,19 +1,35 @@
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::io::Write;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex, RwLock};
 
-use crate::context::Context;
-use crate::error::Result;
-use crate::expression_evaluator::Evaluate;
+use crate::context::{Context, LoopControl, TemplateCallable};
+use crate::error::{Error, ParseErrorKind, RenderErrorKind, Result};
+use crate::expression_evaluator::{CallExpression, Evaluate};
+use crate::filters::FilterExpression;
 use crate::lexer::Token;
 use crate::renderer::ComposedRenderer;
 use crate::renderer::Render;
-use crate::value::{Value, ValuesList, ValuesMap};
+use crate::source::SourceLocationInfo;
+use crate::value::{CallArgs, Callable, Value, ValuesList, ValuesMap};
 
 pub mod parser;
+
+/// The body of a statement, which the parser sets once it reaches the end of the statement.
+fn statement_body<'r, 'a>(
+    body: &'r Option<Rc<ComposedRenderer<'a>>>,
+) -> Result<&'r ComposedRenderer<'a>> {
+    match body {
+        Some(body) => Ok(body),
+        None => Err(Error::from(RenderErrorKind::TemplateNotParsed)),
+    }
+}
+
 pub struct IfStatement<'a> {
     expression: Box<dyn Evaluate + 'a>,
     body: Option<Rc<ComposedRenderer<'a>>>,
-    else_branches: Vec<Statement<'a>>,
+    else_branches: Vec<ElseStatement<'a>>,
 }
 impl<'a> IfStatement<'a> {
     pub fn new(expression: Box<dyn Evaluate + 'a>) -> Self {
@@ -27,24 +43,20 @@ impl<'a> IfStatement<'a> {
         let if_body = body.clone();
         self.body = Some(if_body);
     }
-    pub fn add_else_branch(&mut self, branch: Statement<'a>) {
+    pub fn add_else_branch(&mut self, branch: ElseStatement<'a>) {
         self.else_branches.push(branch);
     }
 }
-impl Render for IfStatement<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
+impl<'a> Render<'a> for IfStatement<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
         let value = self.expression.evaluate(params.clone())?;
         if let Value::Boolean(true) = value {
-            self.body.as_ref().unwrap().render(out, params)?
+            statement_body(&self.body)?.render(out, params)?
         } else {
             for branch in &self.else_branches {
-                if let Statement::Else(else_branch) = branch {
-                    if else_branch.should_render(params.clone()) {
-                        branch.render(out, params)?;
-                        break;
-                    }
-                } else {
-                    todo!()
+                if branch.should_render(params.clone())? {
+                    branch.render(out, params)?;
+                    break;
                 }
             }
         };
@@ -69,17 +81,17 @@ impl<'a> ElseStatement<'a> {
         self.body = Some(else_body);
     }
 
-    fn should_render(&self, values: Context<'_>) -> bool {
-        self.expression.is_none()
-            || match self.expression.as_ref().unwrap().evaluate(values) {
-                Ok(Value::Boolean(boolean)) => boolean,
-                _ => todo!(),
-            }
+    /// Tells whether the branch is taken. As for `if`, a condition that is not a boolean is false.
+    fn should_render(&self, values: Context<'a>) -> Result<bool> {
+        match &self.expression {
+            None => Ok(true),
+            Some(expression) => Ok(matches!(expression.evaluate(values)?, Value::Boolean(true))),
+        }
     }
 }
-impl Render for ElseStatement<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
-        self.body.as_ref().unwrap().render(out, params)
+impl<'a> Render<'a> for ElseStatement<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        statement_body(&self.body)?.render(out, params)
     }
 }
 pub struct WithStatement<'a> {
@@ -98,81 +110,381 @@ impl<'a> WithStatement<'a> {
         self.body = Some(with_body);
     }
 }
-impl Render for WithStatement<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
+impl<'a> Render<'a> for WithStatement<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
         let mut inner_values = params.clone();
         let scope = inner_values.enter_scope();
         for (name, value) in &self.scope_vars {
             let mut scope = scope.write().unwrap();
             scope.insert(name.to_string(), value.evaluate(params.clone())?);
         }
-        self.body.as_ref().unwrap().render(out, inner_values)
+        statement_body(&self.body)?.render(out, inner_values)
+    }
+}
+
+/// Variables a value is assigned to, as in `for key, (first, second) in items`.
+pub enum AssignTarget {
+    Name(String),
+    Tuple(Vec<AssignTarget>),
+    /// Attribute of a namespace, as in `{% set ns.attr = ... %}`.
+    Attribute(String, String),
+}
+
+impl AssignTarget {
+    /// Destructures `value` into the variables of the target.
+    fn unpack(&self, value: Value, bindings: &mut Vec<(String, Value)>) -> Result<()> {
+        match self {
+            AssignTarget::Name(name) => bindings.push((name.clone(), value)),
+            AssignTarget::Tuple(targets) => {
+                if !value.is_iterable() {
+                    return Err(Error::from(RenderErrorKind::InvalidValueType));
+                }
+                let values: ValuesList = value.into();
+                if values.len() != targets.len() {
+                    return Err(Error::from(RenderErrorKind::UnpackMismatch(
+                        values.len(),
+                        targets.len(),
+                    )));
+                }
+                for (target, value) in targets.iter().zip(values) {
+                    target.unpack(value, bindings)?;
+                }
+            }
+            AssignTarget::Attribute(..) => {
+                return Err(Error::from(RenderErrorKind::InvalidOperation));
+            }
+        }
+        Ok(())
+    }
+    /// Assigns `value` to the target from a `set` statement.
+    fn assign(&self, params: &mut Context<'_>, value: Value) -> Result<()> {
+        if let AssignTarget::Attribute(name, attribute) = self {
+            return match params.find(name)? {
+                Value::Namespace(namespace) => namespace.assign(attribute.clone(), value),
+                _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+            };
+        }
+        value.check_depth()?;
+        let mut bindings = vec![];
+        self.unpack(value, &mut bindings)?;
+        for (name, value) in bindings {
+            params.set(name, value);
+        }
+        Ok(())
+    }
+    fn bind(&self, scope: &mut ValuesMap, value: Value) -> Result<()> {
+        let mut bindings = vec![];
+        self.unpack(value, &mut bindings)?;
+        scope.extend(bindings);
+        Ok(())
+    }
+}
+
+pub struct SetStatement<'a> {
+    target: AssignTarget,
+    expression: Box<dyn Evaluate + 'a>,
+}
+
+impl<'a> SetStatement<'a> {
+    pub fn new(target: AssignTarget, expression: Box<dyn Evaluate + 'a>) -> Self {
+        Self { target, expression }
+    }
+}
+impl<'a> Render<'a> for SetStatement<'a> {
+    fn render(&self, _out: &mut dyn Write, mut params: Context<'a>) -> Result<()> {
+        let value = self.expression.evaluate(params.clone())?;
+        self.target.assign(&mut params, value)
+    }
+}
+
+pub struct SetBlockStatement<'a> {
+    target: AssignTarget,
+    filter: Option<FilterExpression<'a>>,
+    body: Option<Rc<ComposedRenderer<'a>>>,
+}
+
+impl<'a> SetBlockStatement<'a> {
+    pub fn new(target: AssignTarget, filter: Option<FilterExpression<'a>>) -> Self {
+        Self {
+            target,
+            filter,
+            body: None,
+        }
+    }
+    fn set_main_body(&mut self, body: Rc<ComposedRenderer<'a>>) {
+        self.body = Some(body);
+    }
+}
+impl<'a> Render<'a> for SetBlockStatement<'a> {
+    fn render(&self, _out: &mut dyn Write, mut params: Context<'a>) -> Result<()> {
+        let mut out: Vec<u8> = Vec::new();
+        statement_body(&self.body)?.render(&mut out, params.clone())?;
+        let mut value = params.output_value(out);
+        if let Some(filter) = &self.filter {
+            value = filter.filter(value, params.clone())?;
+        }
+        self.target.assign(&mut params, value)
+    }
+}
+
+pub struct FilterStatement<'a> {
+    filter: FilterExpression<'a>,
+    body: Option<Rc<ComposedRenderer<'a>>>,
+}
+
+impl<'a> FilterStatement<'a> {
+    pub fn new(filter: FilterExpression<'a>) -> Self {
+        Self { filter, body: None }
+    }
+    fn set_main_body(&mut self, body: Rc<ComposedRenderer<'a>>) {
+        self.body = Some(body);
+    }
+}
+impl<'a> Render<'a> for FilterStatement<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        let mut body: Vec<u8> = Vec::new();
+        statement_body(&self.body)?.render(&mut body, params.clone())?;
+        let value = params.output_value(body);
+        let value = self.filter.filter(value, params)?;
+        if let Err(err) = out.write(value.to_string().as_bytes()) {
+            Err(Error::Io(err))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub struct AutoescapeStatement<'a> {
+    expression: Box<dyn Evaluate + 'a>,
+    body: Option<Rc<ComposedRenderer<'a>>>,
+}
+
+impl<'a> AutoescapeStatement<'a> {
+    pub fn new(expression: Box<dyn Evaluate + 'a>) -> Self {
+        Self {
+            expression,
+            body: None,
+        }
+    }
+    fn set_main_body(&mut self, body: Rc<ComposedRenderer<'a>>) {
+        self.body = Some(body);
+    }
+}
+impl<'a> Render<'a> for AutoescapeStatement<'a> {
+    fn render(&self, out: &mut dyn Write, mut params: Context<'a>) -> Result<()> {
+        match self.expression.evaluate(params.clone())? {
+            Value::Boolean(autoescape) => params.set_autoescape(autoescape),
+            _ => return Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+        statement_body(&self.body)?.render(out, params)
+    }
+}
+
+pub struct DoStatement<'a> {
+    expression: Box<dyn Evaluate + 'a>,
+}
+
+impl<'a> DoStatement<'a> {
+    pub fn new(expression: Box<dyn Evaluate + 'a>) -> Self {
+        Self { expression }
+    }
+}
+impl<'a> Render<'a> for DoStatement<'a> {
+    fn render(&self, _out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        self.expression.evaluate(params)?;
+        Ok(())
     }
 }
 
 pub struct ForStatement<'a> {
-    vars: Vec<String>,
+    target: AssignTarget,
     value: Box<dyn Evaluate + 'a>,
+    condition: Option<Box<dyn Evaluate + 'a>>,
+    recursive: bool,
     body: Option<Rc<ComposedRenderer<'a>>>,
+    else_body: Option<Rc<ComposedRenderer<'a>>>,
 }
 
 impl<'a> ForStatement<'a> {
-    pub fn new(vars: Vec<String>, value: Box<dyn Evaluate + 'a>) -> Self {
+    pub fn new(
+        target: AssignTarget,
+        value: Box<dyn Evaluate + 'a>,
+        condition: Option<Box<dyn Evaluate + 'a>>,
+        recursive: bool,
+    ) -> Self {
         Self {
-            vars,
+            target,
             value,
+            condition,
+            recursive,
             body: None,
+            else_body: None,
         }
     }
     fn set_main_body(&mut self, body: Rc<ComposedRenderer<'a>>) {
         let for_body = body.clone();
         self.body = Some(for_body);
     }
-    fn render_loop(
+    fn set_else_body(&mut self, body: Rc<ComposedRenderer<'a>>) {
+        self.else_body = Some(body);
+    }
+    /// Keeps the items for which the loop condition holds.
+    fn filter_items(
         &self,
+        loop_items: ValuesList,
+        scope: &Arc<RwLock<ValuesMap>>,
+        params: &Context<'a>,
+    ) -> Result<ValuesList> {
+        let condition = match &self.condition {
+            Some(condition) => condition,
+            None => return Ok(loop_items),
+        };
+        let mut filtered_items = vec![];
+        for item in loop_items {
+            self.target
+                .bind(&mut scope.write().unwrap(), item.clone())?;
+            if let Value::Boolean(true) = condition.evaluate(params.clone())? {
+                filtered_items.push(item);
+            }
+        }
+        Ok(filtered_items)
+    }
+    fn render_loop(
+        self: &Rc<Self>,
         loop_value: Value,
         out: &mut dyn Write,
-        mut params: Context<'_>,
-        _level: usize,
+        mut params: Context<'a>,
+        level: usize,
     ) -> Result<()> {
-        let loop_items: ValuesList = loop_value.into();
-        let items_size = loop_items.len();
+        // An undefined value, as given by lenient undefined handling, loops over nothing
+        if !loop_value.is_iterable() && !matches!(loop_value, Value::Empty) {
+            return Err(Error::from(RenderErrorKind::InvalidValueType));
+        }
+        let loop_callable = if self.recursive {
+            let callable = LoopCallable {
+                statement: self.clone(),
+                context: params.clone(),
+                level: level + 1,
+            };
+            Some(params.register_callable("loop", Rc::new(callable)))
+        } else {
+            None
+        };
         let context = params.enter_scope();
+        let loop_items = self.filter_items(loop_value.into(), &context, &params)?;
+        if loop_items.is_empty() {
+            params.exit_scope();
+            return match &self.else_body {
+                Some(else_body) => else_body.render(out, params),
+                None => Ok(()),
+            };
+        }
+        let items_size = loop_items.len();
+        let last_changed = Arc::new(Mutex::new(None));
         for (item_idx, item) in loop_items.iter().enumerate() {
             let mut loop_map = ValuesMap::default();
             loop_map.insert("index".to_string(), Value::Integer((item_idx + 1) as i64));
             loop_map.insert("index0".to_string(), Value::Integer(item_idx as i64));
+            loop_map.insert(
+                "revindex".to_string(),
+                Value::Integer((items_size - item_idx) as i64),
+            );
+            loop_map.insert(
+                "revindex0".to_string(),
+                Value::Integer((items_size - item_idx - 1) as i64),
+            );
+            loop_map.insert("length".to_string(), Value::from(items_size));
             loop_map.insert("first".to_string(), Value::Boolean(item_idx == 0));
             loop_map.insert(
                 "last".to_string(),
                 Value::Boolean(item_idx == items_size - 1),
             );
+            // Undefined, rather than missing, around the ends of the loop, as in Jinja2
+            let prev_item = match item_idx {
+                0 => Value::Empty,
+                _ => loop_items[item_idx - 1].clone(),
+            };
+            loop_map.insert("previtem".to_string(), prev_item);
+            let next_item = loop_items.get(item_idx + 1).cloned().unwrap_or_default();
+            loop_map.insert("nextitem".to_string(), next_item);
+            loop_map.insert("cycle".to_string(), loop_cycle(item_idx));
+            loop_map.insert("changed".to_string(), loop_changed(&last_changed));
+            loop_map.insert("depth".to_string(), Value::from(level + 1));
+            loop_map.insert("depth0".to_string(), Value::from(level));
+            // The loop of a recursive for loop is called to render the loop over nested items
+            let loop_value = match &loop_callable {
+                Some(Value::Callable(callable)) => {
+                    Value::Callable(callable.clone().with_attributes(loop_map))
+                }
+                _ => Value::ValuesMap(loop_map),
+            };
 
             {
                 let mut context = context.write().unwrap();
-                if self.vars.len() > 1 {
-                    todo!();
-                } else {
-                    context.insert(self.vars[0].clone(), item.clone());
-                }
-                context.insert("loop".to_string(), Value::ValuesMap(loop_map));
+                self.target.bind(&mut context, item.clone())?;
+                context.insert("loop".to_string(), loop_value);
             }
             params.enter_scope();
-            self.body.as_ref().unwrap().render(out, params.clone())?;
+            statement_body(&self.body)?.render(out, params.clone())?;
             params.exit_scope();
+            if let Some(LoopControl::Break) = params.take_loop_control() {
+                break;
+            }
         }
 
         params.exit_scope();
         Ok(())
     }
 }
-impl Render for ForStatement<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
+/// `loop.cycle(...)`, returning the argument matching the current iteration.
+fn loop_cycle(item_idx: usize) -> Value {
+    Value::Callable(Callable::new("cycle", move |mut args, _context| {
+        if args.positional.is_empty() {
+            return Err(Error::from(RenderErrorKind::InvalidOperation));
+        }
+        let idx = item_idx % args.positional.len();
+        Ok(args.positional.swap_remove(idx))
+    }))
+}
+
+/// `loop.changed(...)`, telling whether the arguments differ from the previous call.
+fn loop_changed(last_changed: &Arc<Mutex<Option<ValuesList>>>) -> Value {
+    let last_changed = last_changed.clone();
+    Value::Callable(Callable::new("changed", move |args, _context| {
+        let mut last_changed = last_changed.lock().unwrap();
+        let changed = last_changed.as_ref() != Some(&args.positional);
+        *last_changed = Some(args.positional);
+        Ok(Value::Boolean(changed))
+    }))
+}
+
+impl<'a> Render<'a> for Rc<ForStatement<'a>> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
         let loop_value = self.value.evaluate(params.clone())?;
         self.render_loop(loop_value, out, params, 0)?;
         Ok(())
     }
 }
+
+/// The `loop` of a recursive for statement, rendering the loop body over other items.
+struct LoopCallable<'a> {
+    statement: Rc<ForStatement<'a>>,
+    context: Context<'a>,
+    level: usize,
+}
+
+impl<'a> TemplateCallable<'a> for LoopCallable<'a> {
+    fn call(&self, mut args: CallArgs, _context: &Context<'a>) -> Result<Value> {
+        if args.positional.is_empty() {
+            return Err(Error::from(RenderErrorKind::InvalidOperation));
+        }
+        let loop_value = args.positional.remove(0);
+        let mut out = vec![];
+        self.statement
+            .render_loop(loop_value, &mut out, self.context.clone(), self.level)?;
+        Ok(self.context.output_value(out))
+    }
+}
 pub struct IncludeStatement<'a> {
     ignore_missing: bool,
     with_context: bool,
@@ -192,14 +504,14 @@ impl<'a> IncludeStatement<'a> {
         }
     }
 }
-impl Render for IncludeStatement<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
+impl<'a> Render<'a> for IncludeStatement<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
         let template_env = params.get_renderer_callback();
         let name = self.expr_name.evaluate(params.clone())?.to_string();
         let template_result = template_env.load_template(&name);
 
         let template = match template_result {
-            Ok(tmp) => tmp,
+            Ok(tmp) => Rc::new(tmp),
             Err(err) => {
                 if self.ignore_missing {
                     return Ok(());
@@ -208,49 +520,403 @@ impl Render for IncludeStatement<'_> {
                 }
             }
         };
+        params.retain_template(template.clone());
         if self.with_context {
             template.render(out, params)
         } else {
-            let mut context = Context::new(ValuesMap::default(), template_env);
-            context.set_global(template_env.globals());
-            template.render(out, context)
+            template.render(out, params.isolated())
+        }
+    }
+}
+
+pub struct ExtendsStatement<'a> {
+    expr_name: Box<dyn Evaluate + 'a>,
+}
+
+impl<'a> ExtendsStatement<'a> {
+    pub fn new(expr_name: Box<dyn Evaluate + 'a>) -> Self {
+        Self { expr_name }
+    }
+}
+impl<'a> Render<'a> for ExtendsStatement<'a> {
+    fn render(&self, _out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        let template_env = params.get_renderer_callback();
+        let name = self.expr_name.evaluate(params.clone())?.to_string();
+        let template = Rc::new(template_env.load_template(&name)?);
+        params.retain_template(template.clone());
+        match params.template_frame() {
+            Some(frame) => frame.set_parent(template),
+            None => Err(Error::from(RenderErrorKind::InvalidOperation)),
+        }
+    }
+}
+
+/// What an import statement binds in the importing template.
+pub enum ImportNames {
+    /// `import "x" as name`: the whole module.
+    Module(String),
+    /// `from "x" import a, b as c`: the given members, along with their alias.
+    Members(Vec<(String, String)>),
+}
+
+pub struct ImportStatement<'a> {
+    with_context: bool,
+    expr_name: Box<dyn Evaluate + 'a>,
+    names: ImportNames,
+}
+
+impl<'a> ImportStatement<'a> {
+    pub fn new(with_context: bool, expr_name: Box<dyn Evaluate + 'a>, names: ImportNames) -> Self {
+        Self {
+            with_context,
+            expr_name,
+            names,
+        }
+    }
+}
+impl<'a> Render<'a> for ImportStatement<'a> {
+    fn render(&self, _out: &mut dyn Write, mut params: Context<'a>) -> Result<()> {
+        let template_env = params.get_renderer_callback();
+        let name = self.expr_name.evaluate(params.clone())?.to_string();
+        let template = Rc::new(template_env.load_template(&name)?);
+        params.retain_template(template.clone());
+        let module_context = if self.with_context {
+            params.clone()
+        } else {
+            params.isolated()
+        };
+        let module = template.render_as_module(module_context)?;
+        match &self.names {
+            ImportNames::Module(alias) => params.set(alias.clone(), Value::ValuesMap(module)),
+            ImportNames::Members(members) => {
+                for (member, alias) in members {
+                    match module.get(member) {
+                        Some(value) => params.set(alias.clone(), value.clone()),
+                        None => {
+                            return Err(Error::from(ParseErrorKind::UndefinedValue(member.clone())))
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub type Blocks<'a> = HashMap<String, Rc<BlockStatement<'a>>>;
+/// Every definition of each block, from the most derived template to the base one.
+pub type BlockChain<'a> = HashMap<String, Vec<Rc<BlockStatement<'a>>>>;
+
+pub struct BlockStatement<'a> {
+    name: String,
+    scoped: bool,
+    required: bool,
+    body: Rc<ComposedRenderer<'a>>,
+}
+
+impl<'a> BlockStatement<'a> {
+    pub fn new(name: String, scoped: bool, required: bool, body: Rc<ComposedRenderer<'a>>) -> Self {
+        Self {
+            name,
+            scoped,
+            required,
+            body,
+        }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+impl<'a> Render<'a> for BlockStatement<'a> {
+    fn render(&self, out: &mut dyn Write, mut params: Context<'a>) -> Result<()> {
+        let frame = match params.template_frame() {
+            Some(frame) => frame.clone(),
+            None => return self.body.render(out, params),
+        };
+        if frame.is_extended() {
+            // The template being extended is in charge of rendering the blocks.
+            return Ok(());
+        }
+        if !self.scoped {
+            params.truncate_scopes(frame.depth);
+        }
+        let blocks = frame.blocks.get(&self.name).cloned().unwrap_or_default();
+        render_block_chain(&blocks, out, params)
+    }
+}
+
+/// Renders the first definition of a block, exposing the next ones through `super()`.
+fn render_block_chain<'a>(
+    blocks: &[Rc<BlockStatement<'a>>],
+    out: &mut dyn Write,
+    mut params: Context<'a>,
+) -> Result<()> {
+    let (block, parents) = match blocks.split_first() {
+        Some(blocks) => blocks,
+        None => return Ok(()),
+    };
+    if block.required {
+        return Err(Error::from(RenderErrorKind::RequiredBlockNotFound(
+            block.name.clone(),
+        )));
+    }
+    params.enter_scope();
+    if !parents.is_empty() {
+        let super_block =
+            params.register_callable("super", Rc::new(BlockCallable(parents.to_vec())));
+        params.set("super".to_string(), super_block);
+    }
+    block.body.render(out, params)
+}
+
+struct BlockCallable<'a>(Vec<Rc<BlockStatement<'a>>>);
+
+impl<'a> TemplateCallable<'a> for BlockCallable<'a> {
+    fn call(&self, _args: CallArgs, context: &Context<'a>) -> Result<Value> {
+        let mut out: Vec<u8> = Vec::new();
+        render_block_chain(&self.0, &mut out, context.clone())?;
+        Ok(context.output_value(out))
+    }
+}
+
+/// Builds the `self` object, whose members render the blocks of the template.
+pub fn blocks_reference<'a>(blocks: &BlockChain<'a>, context: &Context<'a>) -> Value {
+    let reference = blocks
+        .iter()
+        .map(|(name, chain)| {
+            let block = context.register_callable(name, Rc::new(BlockCallable(chain.clone())));
+            (name.clone(), block)
+        })
+        .collect();
+    Value::ValuesMap(reference)
+}
+
+pub struct MacroParam<'a> {
+    name: String,
+    default: Option<Box<dyn Evaluate + 'a>>,
+}
+
+impl<'a> MacroParam<'a> {
+    pub fn new(name: String, default: Option<Box<dyn Evaluate + 'a>>) -> Self {
+        Self { name, default }
+    }
+}
+
+pub struct MacroStatement<'a> {
+    name: String,
+    params: Vec<MacroParam<'a>>,
+    body: Rc<ComposedRenderer<'a>>,
+    /// Whether the body refers to `varargs`, which then takes the extra positional arguments.
+    catch_varargs: Cell<bool>,
+}
+
+impl<'a> MacroStatement<'a> {
+    pub fn new(name: String, params: Vec<MacroParam<'a>>, body: Rc<ComposedRenderer<'a>>) -> Self {
+        Self {
+            name,
+            params,
+            body,
+            catch_varargs: Cell::new(false),
+        }
+    }
+    /// Binds the call arguments to the macro parameters and renders the body.
+    fn invoke(&self, mut args: CallArgs, mut context: Context<'a>) -> Result<Value> {
+        context.enter_scope();
+        if let Some(caller) = args.keyword.remove("caller") {
+            context.set("caller".to_string(), caller);
+        }
+        let mut positional = args.positional.into_iter();
+        for param in self.params.iter() {
+            let value = match positional.next() {
+                Some(_) if args.keyword.contains_key(&param.name) => {
+                    return Err(Error::from(RenderErrorKind::MultipleValues(
+                        param.name.clone(),
+                    )));
+                }
+                Some(value) => value,
+                None => match args.keyword.remove(&param.name) {
+                    Some(value) => value,
+                    None => match &param.default {
+                        Some(default) => default.evaluate(context.clone())?,
+                        None => {
+                            return Err(Error::from(RenderErrorKind::MissingArgument(
+                                param.name.clone(),
+                            )));
+                        }
+                    },
+                },
+            };
+            context.set(param.name.clone(), value);
+        }
+        let varargs: ValuesList = positional.collect();
+        if !varargs.is_empty() && !self.catch_varargs.get() {
+            return Err(Error::from(RenderErrorKind::TooManyArguments(
+                self.name.clone(),
+            )));
+        }
+        context.set("varargs".to_string(), Value::ValuesList(varargs));
+        context.set("kwargs".to_string(), Value::ValuesMap(args.keyword));
+
+        let mut out: Vec<u8> = Vec::new();
+        self.body.render(&mut out, context.clone())?;
+        Ok(context.output_value(out))
+    }
+}
+
+impl<'a> Render<'a> for Rc<MacroStatement<'a>> {
+    fn render(&self, _out: &mut dyn Write, mut params: Context<'a>) -> Result<()> {
+        let value = macro_callable(self, &params);
+        params.set(self.name.clone(), value);
+        Ok(())
+    }
+}
+
+fn macro_callable<'a>(statement: &Rc<MacroStatement<'a>>, context: &Context<'a>) -> Value {
+    let callable = MacroCallable {
+        statement: statement.clone(),
+        context: context.clone(),
+    };
+    context.register_callable(&statement.name, Rc::new(callable))
+}
+
+/// A macro along with the context it was defined in.
+struct MacroCallable<'a> {
+    statement: Rc<MacroStatement<'a>>,
+    context: Context<'a>,
+}
+
+impl<'a> TemplateCallable<'a> for MacroCallable<'a> {
+    fn call(&self, args: CallArgs, _context: &Context<'a>) -> Result<Value> {
+        self.statement.invoke(args, self.context.clone())
+    }
+}
+
+pub struct CallStatement<'a> {
+    caller: Rc<MacroStatement<'a>>,
+    call: CallExpression<'a>,
+}
+
+impl<'a> CallStatement<'a> {
+    pub fn new(caller: Rc<MacroStatement<'a>>, call: CallExpression<'a>) -> Self {
+        Self { caller, call }
+    }
+}
+impl<'a> Render<'a> for CallStatement<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        let caller = macro_callable(&self.caller, &params);
+        let value = self.call.evaluate_with_caller(params, caller)?;
+        if let Err(err) = out.write(value.to_string().as_bytes()) {
+            Err(Error::Io(err))
+        } else {
+            Ok(())
         }
     }
 }
 
+pub struct LoopControlStatement {
+    control: LoopControl,
+}
+
+impl LoopControlStatement {
+    pub(crate) fn new(control: LoopControl) -> Self {
+        Self { control }
+    }
+}
+impl<'a> Render<'a> for LoopControlStatement {
+    fn render(&self, _out: &mut dyn Write, params: Context<'a>) -> Result<()> {
+        params.set_loop_control(self.control);
+        Ok(())
+    }
+}
+
 pub enum Statement<'a> {
     If(IfStatement<'a>),
     Else(ElseStatement<'a>),
-    For(ForStatement<'a>),
+    For(Rc<ForStatement<'a>>),
     With(WithStatement<'a>),
     Include(IncludeStatement<'a>),
+    Extends(ExtendsStatement<'a>),
+    Block(Rc<BlockStatement<'a>>),
+    Macro(Rc<MacroStatement<'a>>),
+    Call(CallStatement<'a>),
+    Import(ImportStatement<'a>),
+    Set(SetStatement<'a>),
+    SetBlock(SetBlockStatement<'a>),
+    Filter(FilterStatement<'a>),
+    Autoescape(AutoescapeStatement<'a>),
+    Do(DoStatement<'a>),
+    LoopControl(LoopControlStatement),
 }
 impl<'a> Statement<'a> {
-    pub fn set_main_body(&mut self, body: Rc<ComposedRenderer<'a>>) {
+    pub fn set_main_body(&mut self, body: Rc<ComposedRenderer<'a>>) -> Result<()> {
         match self {
             Statement::If(statement) => statement.set_main_body(body),
             Statement::Else(statement) => statement.set_main_body(body),
-            Statement::For(statement) => statement.set_main_body(body),
+            Statement::For(statement) => for_statement(statement)?.set_main_body(body),
             Statement::With(statement) => statement.set_main_body(body),
-            _ => unreachable!(),
+            Statement::SetBlock(statement) => statement.set_main_body(body),
+            Statement::Filter(statement) => statement.set_main_body(body),
+            Statement::Autoescape(statement) => statement.set_main_body(body),
+            _ => {
+                return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                    SourceLocationInfo::default(),
+                )))
+            }
         }
+        Ok(())
     }
-    pub fn add_else_branch(&mut self, branch: Statement<'a>) {
+    pub fn set_else_body(&mut self, body: Rc<ComposedRenderer<'a>>) -> Result<()> {
         match self {
-            Statement::If(statement) => statement.add_else_branch(branch),
-            Statement::Else(_statement) => todo!(),
-            _ => unreachable!(),
+            Statement::For(statement) => for_statement(statement)?.set_else_body(body),
+            _ => {
+                return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                    SourceLocationInfo::default(),
+                )))
+            }
+        }
+        Ok(())
+    }
+    pub fn add_else_branch(&mut self, branch: Statement<'a>) -> Result<()> {
+        match (self, branch) {
+            (Statement::If(statement), Statement::Else(branch)) => {
+                statement.add_else_branch(branch);
+                Ok(())
+            }
+            _ => Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::default(),
+            ))),
         }
     }
 }
-impl Render for Statement<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
+/// The `for` statement being parsed, which nothing else refers to yet.
+fn for_statement<'r, 'a>(
+    statement: &'r mut Rc<ForStatement<'a>>,
+) -> Result<&'r mut ForStatement<'a>> {
+    Rc::get_mut(statement).ok_or_else(|| {
+        Error::from(ParseErrorKind::UnexpectedStatement(
+            SourceLocationInfo::default(),
+        ))
+    })
+}
+impl<'a> Render<'a> for Statement<'a> {
+    fn render(&self, out: &mut dyn Write, params: Context<'a>) -> Result<()> {
         match self {
             Statement::If(statement) => statement.render(out, params),
             Statement::Else(statement) => statement.render(out, params),
             Statement::For(statement) => statement.render(out, params),
             Statement::With(statement) => statement.render(out, params),
             Statement::Include(statement) => statement.render(out, params),
+            Statement::Extends(statement) => statement.render(out, params),
+            Statement::Block(statement) => statement.render(out, params),
+            Statement::Macro(statement) => statement.render(out, params),
+            Statement::Call(statement) => statement.render(out, params),
+            Statement::Import(statement) => statement.render(out, params),
+            Statement::Set(statement) => statement.render(out, params),
+            Statement::SetBlock(statement) => statement.render(out, params),
+            Statement::Filter(statement) => statement.render(out, params),
+            Statement::Autoescape(statement) => statement.render(out, params),
+            Statement::Do(statement) => statement.render(out, params),
+            Statement::LoopControl(statement) => statement.render(out, params),
         }
     }
 }
@@ -261,6 +927,9 @@ pub struct StatementInfo<'a> {
     compositions: Vec<Rc<ComposedRenderer<'a>>>,
     _token: Option<Token<'a>>,
     renderer: Option<Statement<'a>>,
+    pub blocks: Blocks<'a>,
+    /// Where the statement starts in the template, to report it when it is left open.
+    pub position: Option<usize>,
 }
 
 pub enum StatementInfoType {
@@ -269,6 +938,12 @@ pub enum StatementInfoType {
     ElseIfStatement,
     ForStatement,
     WithStatement,
+    BlockStatement,
+    MacroStatement,
+    CallStatement,
+    SetStatement,
+    FilterStatement,
+    AutoescapeStatement,
 }
 
 impl<'a> StatementInfo<'a> {
@@ -285,6 +960,35 @@ impl<'a> StatementInfo<'a> {
             current_composition,
             compositions,
             renderer: None,
+            blocks: Blocks::default(),
+            position: None,
+        }
+    }
+    /// Notes that the body of the macro, or of the `call` block, refers to `varargs`. Returns
+    /// `false` for other statements.
+    pub fn catch_varargs(&self) -> bool {
+        let caller = match &self.renderer {
+            Some(Statement::Macro(statement)) => statement,
+            Some(Statement::Call(statement)) => &statement.caller,
+            _ => return false,
+        };
+        caller.catch_varargs.set(true);
+        true
+    }
+    /// The tag closing the statement, or `None` for the template itself and for `else`
+    /// branches, which are closed along with their statement.
+    pub fn end_tag(&self) -> Option<&'static str> {
+        match self.mode {
+            StatementInfoType::TemplateRoot | StatementInfoType::ElseIfStatement => None,
+            StatementInfoType::IfStatement => Some("endif"),
+            StatementInfoType::ForStatement => Some("endfor"),
+            StatementInfoType::WithStatement => Some("endwith"),
+            StatementInfoType::BlockStatement => Some("endblock"),
+            StatementInfoType::MacroStatement => Some("endmacro"),
+            StatementInfoType::CallStatement => Some("endcall"),
+            StatementInfoType::SetStatement => Some("endset"),
+            StatementInfoType::FilterStatement => Some("endfilter"),
+            StatementInfoType::AutoescapeStatement => Some("endautoescape"),
         }
     }
 }
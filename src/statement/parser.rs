@@ -1,33 +1,40 @@
 use std::rc::Rc;
+use std::sync::Arc;
 
 use logos::{Lexer, Logos};
 
+use crate::context::LoopControl;
 use crate::error::{Error, ParseError, ParseErrorKind, Result};
 use crate::expression_parser::ExpressionParser;
-use crate::lexer::{PeekableLexer, Token};
+use crate::lexer::{PeekableLexer, Token, MAX_NESTING_DEPTH};
 use crate::renderer::ComposedRenderer;
 use crate::source::SourceLocationInfo;
 use crate::statement::Evaluate;
+use crate::template_env::{Registry, Settings};
 
 use super::{
-    ElseStatement, ForStatement, IfStatement, IncludeStatement, Statement, StatementInfo,
-    StatementInfoList, StatementInfoType, WithStatement,
+    AssignTarget, AutoescapeStatement, BlockStatement, CallStatement, DoStatement, ElseStatement,
+    ExtendsStatement, FilterStatement, ForStatement, IfStatement, ImportNames, ImportStatement,
+    IncludeStatement, LoopControlStatement, MacroParam, MacroStatement, SetBlockStatement,
+    SetStatement, Statement, StatementInfo, StatementInfoList, StatementInfoType, WithStatement,
 };
 
 pub struct StatementParser;
 
 impl StatementParser {
-    pub fn parse<'a>(text: &'a str, statementinfo_list: &mut StatementInfoList<'a>) -> Result<()> {
-        let lexer: Lexer<'_, Token<'a>> = Token::lexer(text);
-        let mut lexer = PeekableLexer::new(lexer);
+    pub fn parse<'a>(
+        text: &'a str,
+        statementinfo_list: &mut StatementInfoList<'a>,
+        settings: &Settings,
+        registry: Arc<Registry>,
+    ) -> Result<()> {
+        let lexer: Lexer<'_, Token<'a>> = Token::lexer_with_extras(text, registry);
+        let mut lexer = PeekableLexer::new(lexer, settings.stack_budget);
         let tok = lexer.next();
 
-        match tok {
+        let result = match tok {
             Some(Ok(Token::If)) => StatementParser::parse_if(&mut lexer, statementinfo_list),
-            Some(Ok(Token::Else)) => {
-                StatementParser::parse_else(statementinfo_list);
-                Ok(())
-            }
+            Some(Ok(Token::Else)) => StatementParser::parse_else(&mut lexer, statementinfo_list),
             Some(Ok(Token::EndIf)) => StatementParser::parse_endif(&mut lexer, statementinfo_list),
             Some(Ok(Token::ElIf)) => StatementParser::parse_elif(&mut lexer, statementinfo_list),
             Some(Ok(Token::For)) => StatementParser::parse_for(&mut lexer, statementinfo_list),
@@ -41,6 +48,61 @@ impl StatementParser {
             Some(Ok(Token::Include)) => {
                 StatementParser::parse_include(&mut lexer, statementinfo_list)
             }
+            Some(Ok(Token::Extends)) => {
+                StatementParser::parse_extends(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::Block)) => StatementParser::parse_block(&mut lexer, statementinfo_list),
+            Some(Ok(Token::EndBlock)) => {
+                StatementParser::parse_endblock(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::Macro)) => StatementParser::parse_macro(&mut lexer, statementinfo_list),
+            Some(Ok(Token::EndMacro)) => {
+                StatementParser::parse_endmacro(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::Import)) => {
+                StatementParser::parse_import(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::From)) => StatementParser::parse_from(&mut lexer, statementinfo_list),
+            Some(Ok(Token::Set)) => StatementParser::parse_set(&mut lexer, statementinfo_list),
+            Some(Ok(Token::EndSet)) => {
+                StatementParser::parse_endset(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::Filter)) => {
+                StatementParser::parse_filter(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::EndFilter)) => {
+                StatementParser::parse_endfilter(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::Do)) => {
+                if !settings.extensions.do_ext {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseError::new(
+                        ParseErrorKind::ExtensionDisabled,
+                        Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                    )));
+                }
+                StatementParser::parse_do(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::Autoescape)) => {
+                StatementParser::parse_autoescape(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(Token::EndAutoescape)) => {
+                StatementParser::parse_endautoescape(&mut lexer, statementinfo_list)
+            }
+            Some(Ok(token @ (Token::Break | Token::Continue))) => {
+                if !settings.extensions.loop_controls {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseError::new(
+                        ParseErrorKind::ExtensionDisabled,
+                        Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                    )));
+                }
+                StatementParser::parse_loop_control(&mut lexer, statementinfo_list, token)
+            }
+            Some(Ok(Token::Call)) => StatementParser::parse_call(&mut lexer, statementinfo_list),
+            Some(Ok(Token::EndCall)) => {
+                StatementParser::parse_endcall(&mut lexer, statementinfo_list)
+            }
             Some(_) => {
                 let range = lexer.span();
                 Err(Error::from(ParseError::new(
@@ -48,8 +110,22 @@ impl StatementParser {
                     Some(SourceLocationInfo::new_with_range(range.start, range.end)),
                 )))
             }
-            _ => todo!(),
+            None => {
+                let range = lexer.span();
+                Err(Error::from(ParseError::new(
+                    ParseErrorKind::ExpectedToken("statement"),
+                    Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                )))
+            }
+        };
+        if statementinfo_list.len() > MAX_NESTING_DEPTH {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::NestingTooDeep,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
         }
+        result
     }
     fn parse_if<'a>(
         lexer: &mut PeekableLexer<'a, Token<'a>>,
@@ -72,6 +148,7 @@ impl StatementParser {
         lexer: &mut PeekableLexer<'a, Token<'a>>,
         statementinfo_list: &mut StatementInfoList<'a>,
     ) -> Result<()> {
+        StatementParser::check_branch(lexer, statementinfo_list, false)?;
         let value = ExpressionParser::full_expresion_parser(lexer)?;
         let composed_renderer = Rc::new(ComposedRenderer::new());
         let renderer = Statement::Else(ElseStatement::new(Some(Box::new(value))));
@@ -85,7 +162,11 @@ impl StatementParser {
         Ok(())
     }
 
-    fn parse_else(statementinfo_list: &mut StatementInfoList<'_>) {
+    fn parse_else<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        StatementParser::check_branch(lexer, statementinfo_list, true)?;
         let composed_renderer = Rc::new(ComposedRenderer::new());
         let renderer = Statement::Else(ElseStatement::new(None));
         let mut statement_info = StatementInfo::new(
@@ -95,6 +176,33 @@ impl StatementParser {
         );
         statement_info.renderer = Some(renderer);
         statementinfo_list.push(statement_info);
+        Ok(())
+    }
+    /// Fails unless the branch follows an `if` or an `elif`, or, for an `else`, a `for`.
+    fn check_branch<'a>(
+        lexer: &PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &StatementInfoList<'a>,
+        is_else: bool,
+    ) -> Result<()> {
+        let follows_statement = match statementinfo_list.last() {
+            Some(info) => match (&info.mode, &info.renderer) {
+                (StatementInfoType::IfStatement, _) => true,
+                (StatementInfoType::ForStatement, _) => is_else,
+                (StatementInfoType::ElseIfStatement, Some(Statement::Else(else_statement))) => {
+                    else_statement.expression.is_some()
+                }
+                _ => false,
+            },
+            None => false,
+        };
+        if follows_statement {
+            Ok(())
+        } else {
+            let range = lexer.span();
+            Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )))
+        }
     }
     fn parse_endif<'a>(
         lexer: &mut PeekableLexer<'a, Token<'a>>,
@@ -117,17 +225,22 @@ impl StatementParser {
                 }
                 StatementInfoType::ElseIfStatement => {
                     let mut renderer = info.renderer.unwrap();
-                    renderer.set_main_body(info.compositions.remove(0));
+                    renderer.set_main_body(info.compositions.remove(0))?;
                     else_branches.push(renderer);
                 }
-                _ => todo!(),
+                _ => {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                        SourceLocationInfo::new_with_range(range.start, range.end),
+                    )));
+                }
             }
         }
         let mut renderer = info.renderer.unwrap();
         let body = info.compositions.remove(0);
-        renderer.set_main_body(body);
+        renderer.set_main_body(body)?;
         for else_branch in else_branches.into_iter().rev() {
-            renderer.add_else_branch(else_branch);
+            renderer.add_else_branch(else_branch)?;
         }
         statementinfo_list
             .last_mut()
@@ -140,24 +253,22 @@ impl StatementParser {
         lexer: &mut PeekableLexer<'a, Token<'a>>,
         statementinfo_list: &mut StatementInfoList<'a>,
     ) -> Result<()> {
-        let mut vars = vec![];
-        loop {
-            if let Some(Ok(Token::Identifier(identifier))) = lexer.next() {
-                vars.push(identifier.to_string());
-            } else {
-                let range = lexer.span();
-                return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
-                    SourceLocationInfo::new_with_range(range.start, range.end),
-                )));
-            }
-            if let Some(Ok(Token::Comma)) = lexer.peek() {
+        let target = StatementParser::parse_assign_target(lexer)?;
+        if let Some(Ok(Token::In)) = lexer.next() {
+            let expression = ExpressionParser::unconditional_expression_parser(lexer)?;
+            let condition: Option<Box<dyn Evaluate + 'a>> =
+                if let Some(Ok(Token::If)) = lexer.peek() {
+                    lexer.next();
+                    Some(Box::new(ExpressionParser::full_expresion_parser(lexer)?))
+                } else {
+                    None
+                };
+            let recursive = if let Some(Ok(Token::Recursive)) = lexer.peek() {
                 lexer.next();
+                true
             } else {
-                break;
-            }
-        }
-        if let Some(Ok(Token::In)) = lexer.next() {
-            let expression = ExpressionParser::full_expresion_parser(lexer)?;
+                false
+            };
             if lexer.next().is_some() {
                 let range = lexer.span();
                 Err(Error::from(ParseError::new(
@@ -166,7 +277,12 @@ impl StatementParser {
                 )))
             } else {
                 let composed_renderer = Rc::new(ComposedRenderer::new());
-                let renderer = Statement::For(ForStatement::new(vars, Box::new(expression)));
+                let renderer = Statement::For(Rc::new(ForStatement::new(
+                    target,
+                    Box::new(expression),
+                    condition,
+                    recursive,
+                )));
                 let mut statement_info = StatementInfo::new(
                     StatementInfoType::ForStatement,
                     Some(Token::For),
@@ -195,10 +311,30 @@ impl StatementParser {
             )));
         }
         let mut info = statementinfo_list.pop().unwrap();
+        let mut else_body = None;
+        if let StatementInfoType::ElseIfStatement = info.mode {
+            match info.renderer {
+                Some(Statement::Else(ref else_statement))
+                    if else_statement.expression.is_none() && !statementinfo_list.is_empty() =>
+                {
+                    else_body = Some(info.compositions.remove(0));
+                    info = statementinfo_list.pop().unwrap();
+                }
+                _ => {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                        SourceLocationInfo::new_with_range(range.start, range.end),
+                    )));
+                }
+            }
+        }
         if let StatementInfoType::ForStatement = info.mode {
             let mut renderer = info.renderer.unwrap();
             let body = info.compositions.remove(0);
-            renderer.set_main_body(body);
+            renderer.set_main_body(body)?;
+            if let Some(else_body) = else_body {
+                renderer.set_else_body(else_body)?;
+            }
             statementinfo_list
                 .last_mut()
                 .unwrap()
@@ -273,7 +409,7 @@ impl StatementParser {
         if let StatementInfoType::WithStatement = info.mode {
             let mut renderer = info.renderer.unwrap();
             let body = info.compositions.remove(0);
-            renderer.set_main_body(body);
+            renderer.set_main_body(body)?;
             statementinfo_list
                 .last_mut()
                 .unwrap()
@@ -299,7 +435,6 @@ impl StatementParser {
         }
         let expr = ExpressionParser::full_expresion_parser(lexer)?;
         let mut is_ignore_missing = false;
-        let mut is_with_context = true;
 
         if let Some(Ok(Token::Ignore)) = lexer.peek() {
             lexer.next();
@@ -315,30 +450,119 @@ impl StatementParser {
             lexer.next();
         }
 
-        match lexer.next() {
-            Some(Ok(Token::With)) => {
-                if let Some(Ok(Token::Context)) = lexer.peek() {
-                    lexer.next();
-                } else {
+        let is_with_context = StatementParser::parse_context_modifier(lexer, true)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let renderer = Statement::Include(IncludeStatement::new(
+            is_ignore_missing,
+            is_with_context,
+            Box::new(expr),
+        ));
+        statementinfo_list
+            .last_mut()
+            .unwrap()
+            .current_composition
+            .add_renderer(Box::new(renderer));
+
+        Ok(())
+    }
+    fn parse_extends<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.is_empty() {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let expr = ExpressionParser::full_expresion_parser(lexer)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let renderer = Statement::Extends(ExtendsStatement::new(Box::new(expr)));
+        statementinfo_list
+            .last_mut()
+            .unwrap()
+            .current_composition
+            .add_renderer(Box::new(renderer));
+        Ok(())
+    }
+    fn parse_block<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        let name = if let Some(Ok(Token::Identifier(name))) = lexer.next() {
+            name.to_string()
+        } else {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        };
+        let mut scoped = false;
+        let mut required = false;
+        loop {
+            match lexer.next() {
+                Some(Ok(Token::Scoped)) => scoped = true,
+                Some(Ok(Token::Identifier(modifier))) if modifier == "required" => required = true,
+                None => break,
+                _ => {
                     let range = lexer.span();
                     return Err(Error::from(ParseError::new(
-                        ParseErrorKind::ExpectedToken("context"),
+                        ParseErrorKind::UnexpectedToken,
                         Some(SourceLocationInfo::new_with_range(range.start, range.end)),
                     )));
                 }
             }
-            Some(Ok(Token::Without)) => {
-                is_with_context = false;
-                if let Some(Ok(Token::Context)) = lexer.peek() {
-                    lexer.next();
-                } else {
-                    let range = lexer.span();
-                    return Err(Error::from(ParseError::new(
-                        ParseErrorKind::ExpectedToken("context"),
-                        Some(SourceLocationInfo::new_with_range(range.start, range.end)),
-                    )));
-                }
+        }
+        let composed_renderer = Rc::new(ComposedRenderer::new());
+        let renderer = Statement::Block(Rc::new(BlockStatement::new(
+            name,
+            scoped,
+            required,
+            composed_renderer.clone(),
+        )));
+        let mut statement_info = StatementInfo::new(
+            StatementInfoType::BlockStatement,
+            Some(Token::Block),
+            composed_renderer,
+        );
+        statement_info.renderer = Some(renderer);
+        statementinfo_list.push(statement_info);
+        Ok(())
+    }
+    fn parse_endblock<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.len() <= 1 {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let info = statementinfo_list.pop().unwrap();
+        let block = match (info.mode, info.renderer) {
+            (StatementInfoType::BlockStatement, Some(Statement::Block(block))) => block,
+            _ => {
+                let range = lexer.span();
+                return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                    SourceLocationInfo::new_with_range(range.start, range.end),
+                )));
             }
+        };
+        match lexer.next() {
+            Some(Ok(Token::Identifier(name))) if name == block.name() => {}
             None => {}
             _ => {
                 let range = lexer.span();
@@ -355,17 +579,638 @@ impl StatementParser {
                 Some(SourceLocationInfo::new_with_range(range.start, range.end)),
             )));
         }
-        let renderer = Statement::Include(IncludeStatement::new(
-            is_ignore_missing,
-            is_with_context,
-            Box::new(expr),
-        ));
+        let blocks = &mut statementinfo_list.first_mut().unwrap().blocks;
+        if blocks.contains_key(block.name()) {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::DuplicateBlock(block.name().to_string()),
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        blocks.insert(block.name().to_string(), block.clone());
         statementinfo_list
             .last_mut()
             .unwrap()
             .current_composition
-            .add_renderer(Box::new(renderer));
-
+            .add_renderer(Box::new(Statement::Block(block)));
+        Ok(())
+    }
+    fn parse_macro<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        let name = if let Some(Ok(Token::Identifier(name))) = lexer.next() {
+            name.to_string()
+        } else {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        };
+        let params = StatementParser::parse_macro_params(lexer)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let composed_renderer = Rc::new(ComposedRenderer::new());
+        let renderer = Statement::Macro(Rc::new(MacroStatement::new(
+            name,
+            params,
+            composed_renderer.clone(),
+        )));
+        let mut statement_info = StatementInfo::new(
+            StatementInfoType::MacroStatement,
+            Some(Token::Macro),
+            composed_renderer,
+        );
+        statement_info.renderer = Some(renderer);
+        statementinfo_list.push(statement_info);
         Ok(())
     }
+    fn parse_macro_params<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+    ) -> Result<Vec<MacroParam<'a>>> {
+        let mut params = vec![];
+        if let Some(Ok(Token::LBracket)) = lexer.next() {
+        } else {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::ExpectedBracket("("),
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        if let Some(Ok(Token::RBracket)) = lexer.peek() {
+            lexer.next();
+            return Ok(params);
+        }
+        loop {
+            let name = if let Some(Ok(Token::Identifier(name))) = lexer.next() {
+                name.to_string()
+            } else {
+                let range = lexer.span();
+                return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                    SourceLocationInfo::new_with_range(range.start, range.end),
+                )));
+            };
+            let default: Option<Box<dyn Evaluate + 'a>> =
+                if let Some(Ok(Token::Assign)) = lexer.peek() {
+                    lexer.next();
+                    Some(Box::new(ExpressionParser::full_expresion_parser(lexer)?))
+                } else {
+                    None
+                };
+            params.push(MacroParam::new(name, default));
+            match lexer.next() {
+                Some(Ok(Token::Comma)) => {}
+                Some(Ok(Token::RBracket)) => break,
+                _ => {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseError::new(
+                        ParseErrorKind::ExpectedBracket(")"),
+                        Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                    )));
+                }
+            }
+        }
+        Ok(params)
+    }
+    fn parse_endmacro<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.len() <= 1 {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let info = statementinfo_list.pop().unwrap();
+        if let (StatementInfoType::MacroStatement, Some(renderer)) = (info.mode, info.renderer) {
+            if lexer.next().is_some() {
+                let range = lexer.span();
+                return Err(Error::from(ParseError::new(
+                    ParseErrorKind::UnexpectedToken,
+                    Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                )));
+            }
+            statementinfo_list
+                .last_mut()
+                .unwrap()
+                .current_composition
+                .add_renderer(Box::new(renderer));
+            Ok(())
+        } else {
+            let range = lexer.span();
+            Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )))
+        }
+    }
+    fn parse_call<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        let params = if let Some(Ok(Token::LBracket)) = lexer.peek() {
+            StatementParser::parse_macro_params(lexer)?
+        } else {
+            vec![]
+        };
+        let call = ExpressionParser::call_expression_parser(lexer)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let composed_renderer = Rc::new(ComposedRenderer::new());
+        let caller = MacroStatement::new("caller".to_string(), params, composed_renderer.clone());
+        let renderer = Statement::Call(CallStatement::new(Rc::new(caller), call));
+        let mut statement_info = StatementInfo::new(
+            StatementInfoType::CallStatement,
+            Some(Token::Call),
+            composed_renderer,
+        );
+        statement_info.renderer = Some(renderer);
+        statementinfo_list.push(statement_info);
+        Ok(())
+    }
+    fn parse_endcall<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.len() <= 1 {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let info = statementinfo_list.pop().unwrap();
+        if let (StatementInfoType::CallStatement, Some(renderer)) = (info.mode, info.renderer) {
+            if lexer.next().is_some() {
+                let range = lexer.span();
+                return Err(Error::from(ParseError::new(
+                    ParseErrorKind::UnexpectedToken,
+                    Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                )));
+            }
+            statementinfo_list
+                .last_mut()
+                .unwrap()
+                .current_composition
+                .add_renderer(Box::new(renderer));
+            Ok(())
+        } else {
+            let range = lexer.span();
+            Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )))
+        }
+    }
+    /// Parses an optional `with context` or `without context` modifier.
+    fn parse_context_modifier<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        default: bool,
+    ) -> Result<bool> {
+        let with_context = match lexer.peek() {
+            Some(Ok(Token::With)) => true,
+            Some(Ok(Token::Without)) => false,
+            _ => return Ok(default),
+        };
+        lexer.next();
+        if let Some(Ok(Token::Context)) = lexer.next() {
+            Ok(with_context)
+        } else {
+            let range = lexer.span();
+            Err(Error::from(ParseError::new(
+                ParseErrorKind::ExpectedToken("context"),
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )))
+        }
+    }
+    fn parse_import<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.is_empty() {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let expr = ExpressionParser::full_expresion_parser(lexer)?;
+        if let Some(Ok(Token::As)) = lexer.next() {
+        } else {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::ExpectedToken("as"),
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let alias = if let Some(Ok(Token::Identifier(alias))) = lexer.next() {
+            alias.to_string()
+        } else {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        };
+        let is_with_context = StatementParser::parse_context_modifier(lexer, false)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let renderer = Statement::Import(ImportStatement::new(
+            is_with_context,
+            Box::new(expr),
+            ImportNames::Module(alias),
+        ));
+        statementinfo_list
+            .last_mut()
+            .unwrap()
+            .current_composition
+            .add_renderer(Box::new(renderer));
+        Ok(())
+    }
+    fn parse_from<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.is_empty() {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let expr = ExpressionParser::full_expresion_parser(lexer)?;
+        if let Some(Ok(Token::Import)) = lexer.next() {
+        } else {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::ExpectedToken("import"),
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let mut members = vec![];
+        loop {
+            let member = if let Some(Ok(Token::Identifier(member))) = lexer.next() {
+                member.to_string()
+            } else {
+                let range = lexer.span();
+                return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                    SourceLocationInfo::new_with_range(range.start, range.end),
+                )));
+            };
+            let alias = if let Some(Ok(Token::As)) = lexer.peek() {
+                lexer.next();
+                if let Some(Ok(Token::Identifier(alias))) = lexer.next() {
+                    alias.to_string()
+                } else {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                        SourceLocationInfo::new_with_range(range.start, range.end),
+                    )));
+                }
+            } else {
+                member.clone()
+            };
+            members.push((member, alias));
+            if let Some(Ok(Token::Comma)) = lexer.peek() {
+                lexer.next();
+            } else {
+                break;
+            }
+        }
+        let is_with_context = StatementParser::parse_context_modifier(lexer, false)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let renderer = Statement::Import(ImportStatement::new(
+            is_with_context,
+            Box::new(expr),
+            ImportNames::Members(members),
+        ));
+        statementinfo_list
+            .last_mut()
+            .unwrap()
+            .current_composition
+            .add_renderer(Box::new(renderer));
+        Ok(())
+    }
+    fn parse_set<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        let mut target = StatementParser::parse_assign_target(lexer)?;
+        if let (Some(Ok(Token::Point)), AssignTarget::Name(name)) = (lexer.peek(), &target) {
+            let name = name.clone();
+            lexer.next();
+            target = match lexer.next() {
+                Some(Ok(Token::Identifier(attribute))) => {
+                    AssignTarget::Attribute(name, attribute.to_string())
+                }
+                _ => {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                        SourceLocationInfo::new_with_range(range.start, range.end),
+                    )));
+                }
+            };
+        }
+        let renderer = match (lexer.next(), target) {
+            (Some(Ok(Token::Assign)), target) => {
+                let expression = ExpressionParser::full_expresion_parser(lexer)?;
+                Statement::Set(SetStatement::new(target, Box::new(expression)))
+            }
+            (None, target @ (AssignTarget::Name(_) | AssignTarget::Attribute(..))) => {
+                Statement::SetBlock(SetBlockStatement::new(target, None))
+            }
+            (
+                Some(Ok(Token::Pipe)),
+                target @ (AssignTarget::Name(_) | AssignTarget::Attribute(..)),
+            ) => {
+                let filter = ExpressionParser::parse_filter_expression(lexer)?;
+                Statement::SetBlock(SetBlockStatement::new(target, Some(filter)))
+            }
+            _ => {
+                let range = lexer.span();
+                return Err(Error::from(ParseError::new(
+                    ParseErrorKind::ExpectedToken("="),
+                    Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                )));
+            }
+        };
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        if let Statement::SetBlock(_) = renderer {
+            let composed_renderer = Rc::new(ComposedRenderer::new());
+            let mut statement_info = StatementInfo::new(
+                StatementInfoType::SetStatement,
+                Some(Token::Set),
+                composed_renderer,
+            );
+            statement_info.renderer = Some(renderer);
+            statementinfo_list.push(statement_info);
+        } else {
+            statementinfo_list
+                .last_mut()
+                .unwrap()
+                .current_composition
+                .add_renderer(Box::new(renderer));
+        }
+        Ok(())
+    }
+    fn parse_endset<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.len() <= 1 {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let mut info = statementinfo_list.pop().unwrap();
+        if let StatementInfoType::SetStatement = info.mode {
+            let mut renderer = info.renderer.unwrap();
+            let body = info.compositions.remove(0);
+            renderer.set_main_body(body)?;
+            statementinfo_list
+                .last_mut()
+                .unwrap()
+                .current_composition
+                .add_renderer(Box::new(renderer));
+            Ok(())
+        } else {
+            let range = lexer.span();
+            Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )))
+        }
+    }
+    fn parse_filter<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        let filter = ExpressionParser::parse_filter_expression(lexer)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let composed_renderer = Rc::new(ComposedRenderer::new());
+        let renderer = Statement::Filter(FilterStatement::new(filter));
+        let mut statement_info = StatementInfo::new(
+            StatementInfoType::FilterStatement,
+            Some(Token::Filter),
+            composed_renderer,
+        );
+        statement_info.renderer = Some(renderer);
+        statementinfo_list.push(statement_info);
+        Ok(())
+    }
+    fn parse_endfilter<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.len() <= 1 {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let mut info = statementinfo_list.pop().unwrap();
+        if let StatementInfoType::FilterStatement = info.mode {
+            let mut renderer = info.renderer.unwrap();
+            let body = info.compositions.remove(0);
+            renderer.set_main_body(body)?;
+            statementinfo_list
+                .last_mut()
+                .unwrap()
+                .current_composition
+                .add_renderer(Box::new(renderer));
+            Ok(())
+        } else {
+            let range = lexer.span();
+            Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )))
+        }
+    }
+    fn parse_autoescape<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        let expression = ExpressionParser::full_expresion_parser(lexer)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let composed_renderer = Rc::new(ComposedRenderer::new());
+        let renderer = Statement::Autoescape(AutoescapeStatement::new(Box::new(expression)));
+        let mut statement_info = StatementInfo::new(
+            StatementInfoType::AutoescapeStatement,
+            Some(Token::Autoescape),
+            composed_renderer,
+        );
+        statement_info.renderer = Some(renderer);
+        statementinfo_list.push(statement_info);
+        Ok(())
+    }
+    fn parse_endautoescape<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        if statementinfo_list.len() <= 1 {
+            let range = lexer.span();
+            return Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )));
+        }
+        let mut info = statementinfo_list.pop().unwrap();
+        if let StatementInfoType::AutoescapeStatement = info.mode {
+            let mut renderer = info.renderer.unwrap();
+            let body = info.compositions.remove(0);
+            renderer.set_main_body(body)?;
+            statementinfo_list
+                .last_mut()
+                .unwrap()
+                .current_composition
+                .add_renderer(Box::new(renderer));
+            Ok(())
+        } else {
+            let range = lexer.span();
+            Err(Error::from(ParseErrorKind::UnexpectedStatement(
+                SourceLocationInfo::new_with_range(range.start, range.end),
+            )))
+        }
+    }
+    fn parse_do<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+    ) -> Result<()> {
+        let expression = ExpressionParser::full_expresion_parser(lexer)?;
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let renderer = Statement::Do(DoStatement::new(Box::new(expression)));
+        statementinfo_list
+            .last_mut()
+            .unwrap()
+            .current_composition
+            .add_renderer(Box::new(renderer));
+        Ok(())
+    }
+    fn parse_loop_control<'a>(
+        lexer: &mut PeekableLexer<'a, Token<'a>>,
+        statementinfo_list: &mut StatementInfoList<'a>,
+        token: Token<'a>,
+    ) -> Result<()> {
+        let (control, name) = if let Token::Break = token {
+            (LoopControl::Break, "break")
+        } else {
+            (LoopControl::Continue, "continue")
+        };
+        if !StatementParser::is_inside_loop(statementinfo_list) {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::LoopControlOutsideLoop(name),
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        if lexer.next().is_some() {
+            let range = lexer.span();
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+            )));
+        }
+        let renderer = Statement::LoopControl(LoopControlStatement::new(control));
+        statementinfo_list
+            .last_mut()
+            .unwrap()
+            .current_composition
+            .add_renderer(Box::new(renderer));
+        Ok(())
+    }
+    /// Tells whether the statement being parsed is part of a loop body. The `else` branch of a
+    /// loop and the bodies of macros and call blocks are not.
+    fn is_inside_loop(statementinfo_list: &StatementInfoList<'_>) -> bool {
+        let mut in_else_branch = false;
+        for info in statementinfo_list.iter().rev() {
+            match info.mode {
+                StatementInfoType::ForStatement if !in_else_branch => return true,
+                StatementInfoType::MacroStatement | StatementInfoType::CallStatement => {
+                    return false
+                }
+                _ => {}
+            }
+            in_else_branch = matches!(info.mode, StatementInfoType::ElseIfStatement);
+        }
+        false
+    }
+    /// Parses the variables of a `for` or `set` statement, such as `key, (first, second)`.
+    fn parse_assign_target<'a>(lexer: &mut PeekableLexer<'a, Token<'a>>) -> Result<AssignTarget> {
+        let mut targets = vec![];
+        loop {
+            let target = match lexer.next() {
+                Some(Ok(Token::Identifier(identifier))) => {
+                    AssignTarget::Name(identifier.to_string())
+                }
+                Some(Ok(Token::LBracket)) => {
+                    let target = StatementParser::parse_assign_target(lexer)?;
+                    if let Some(Ok(Token::RBracket)) = lexer.next() {
+                        target
+                    } else {
+                        let range = lexer.span();
+                        return Err(Error::from(ParseError::new(
+                            ParseErrorKind::ExpectedBracket(")"),
+                            Some(SourceLocationInfo::new_with_range(range.start, range.end)),
+                        )));
+                    }
+                }
+                _ => {
+                    let range = lexer.span();
+                    return Err(Error::from(ParseErrorKind::ExpectedIdentifier(
+                        SourceLocationInfo::new_with_range(range.start, range.end),
+                    )));
+                }
+            };
+            targets.push(target);
+            if let Some(Ok(Token::Comma)) = lexer.peek() {
+                lexer.next();
+            } else {
+                break;
+            }
+        }
+        if targets.len() == 1 {
+            Ok(targets.remove(0))
+        } else {
+            Ok(AssignTarget::Tuple(targets))
+        }
+    }
 }
@@ -1,16 +1,21 @@
 use crate::context::Context;
-use crate::error::{Error, Result};
+use crate::error::{Error, RenderErrorKind, Result};
 use crate::renderer::{ComposedRenderer, Render};
+use crate::statement::{self, BlockChain, Blocks};
 use crate::template_env::TemplateEnv;
 use crate::template_parser::TemplateParser;
+use crate::value::ValuesMap;
 use serde::Serialize;
 use std::borrow::Cow;
-use std::io::Write;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
 
 pub struct Template<'a> {
     body: Cow<'a, str>,
     template_env: &'a TemplateEnv<'a>,
     renderer: Option<ComposedRenderer<'a>>,
+    blocks: Blocks<'a>,
     template_name: Option<String>,
 }
 
@@ -19,6 +24,7 @@ impl<'a> Template<'a> {
         Ok(Self {
             template_env,
             renderer: None,
+            blocks: Blocks::default(),
             body: Cow::Borrowed(""),
             template_name: None,
         })
@@ -30,12 +36,18 @@ impl<'a> Template<'a> {
         Ok(Self {
             template_env,
             renderer: None,
+            blocks: Blocks::default(),
             body: Cow::Borrowed(""),
             template_name: Some(template_name),
         })
     }
 
     pub fn parse(&self) -> Result<ComposedRenderer<'a>> {
+        let (renderer, _) = self.parse_with_blocks()?;
+        Ok(renderer)
+    }
+
+    fn parse_with_blocks(&self) -> Result<(ComposedRenderer<'a>, Blocks<'a>)> {
         let mut parser = match &self.body {
             Cow::Borrowed(template_body) => TemplateParser::new(template_body, self.template_env)?,
             Cow::Owned(_template_body_owned) => {
@@ -52,38 +64,149 @@ impl<'a> Template<'a> {
         S: Into<Cow<'a, str>>,
     {
         self.body = tpl_body.into();
-        let renderer = self.parse()?;
+        let (renderer, blocks) = match self.parse_with_blocks() {
+            Err(Error::ParseError(mut parse_error)) => {
+                if let Some(tpl_name) = &self.template_name {
+                    parse_error.location.set_filename(tpl_name.to_owned());
+                }
+                return Err(Error::ParseError(parse_error));
+            }
+            result => result?,
+        };
         self.renderer = Some(renderer);
+        self.blocks = blocks;
 
         Ok(())
     }
 
     pub fn render_as_string(&self, params: impl Serialize) -> Result<String> {
         let mut b: Vec<u8> = Vec::new();
-        let mut context = Context::new(params, self.template_env);
+        let mut context = Context::new(params, self.template_env)?;
         context.set_global(self.template_env.globals());
 
-        self.render(&mut b, context)?;
-        Ok(String::from_utf8(b).expect("Found invalid UTF-8"))
+        let result = self.render(&mut b, context.clone());
+        context.release();
+        result?;
+        String::from_utf8(b)
+            .map_err(|err| Error::from(io::Error::new(io::ErrorKind::InvalidData, err)))
     }
-}
 
-impl Render for Template<'_> {
-    fn render(&self, out: &mut dyn Write, params: Context<'_>) -> Result<()> {
-        if let Some(ref renderer) = self.renderer {
-            let result = renderer.render(out, params);
-            if let Err(Error::ParseError(mut parse_error)) = result {
-                if let Some(tpl_name) = &self.template_name {
-                    let mut source = parse_error.location;
-                    source.set_filename(tpl_name.to_owned());
-                    parse_error.location = source;
-                }
-                Err(Error::ParseError(parse_error))
-            } else {
-                result
+    /// Renders the template with the given blocks, the ones of the templates
+    /// extending this one first, then renders the template it extends, if any.
+    fn render_with_blocks(
+        &self,
+        out: &mut dyn Write,
+        mut params: Context<'a>,
+        blocks: BlockChain<'a>,
+    ) -> Result<()> {
+        let renderer = match self.renderer {
+            Some(ref renderer) => renderer,
+            None => return Err(Error::from(RenderErrorKind::TemplateNotParsed)),
+        };
+        params.check_stack()?;
+        let frame = Rc::new(TemplateFrame::new(blocks, params.depth()));
+        params.set_template_frame(frame.clone());
+        let self_reference = statement::blocks_reference(&frame.blocks, &params);
+        params.set("self".to_string(), self_reference);
+
+        let mut writer = InheritanceWriter {
+            out: &mut *out,
+            frame: &frame,
+        };
+        let result = renderer.render(&mut writer, params.clone());
+        if let Err(Error::ParseError(mut parse_error)) = result {
+            if let Some(tpl_name) = &self.template_name {
+                let mut source = parse_error.location;
+                source.set_filename(tpl_name.to_owned());
+                parse_error.location = source;
+            }
+            return Err(Error::ParseError(parse_error));
+        }
+        result?;
+
+        let parent = frame.parent.borrow_mut().take();
+        if let Some(parent) = parent {
+            let mut blocks = frame.blocks.clone();
+            for (name, block) in parent.blocks.iter() {
+                blocks.entry(name.clone()).or_default().push(block.clone());
             }
+            parent.render_with_blocks(out, params, blocks)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn block_chain(&self) -> BlockChain<'a> {
+        self.blocks
+            .iter()
+            .map(|(name, block)| (name.clone(), vec![block.clone()]))
+            .collect()
+    }
+
+    /// Renders the template discarding its output, and returns the variables
+    /// and macros it defines at the top level.
+    pub(crate) fn render_as_module(&self, mut params: Context<'a>) -> Result<ValuesMap> {
+        let scope = params.enter_scope();
+        self.render_with_blocks(&mut io::sink(), params, self.block_chain())?;
+        let mut module = scope.read().unwrap().clone();
+        module.remove("self");
+        Ok(module)
+    }
+}
+
+impl<'a> Render<'a> for Template<'a> {
+    fn render(&self, out: &mut dyn Write, mut params: Context<'a>) -> Result<()> {
+        params.enter_scope();
+        self.render_with_blocks(out, params, self.block_chain())
+    }
+}
+
+/// Inheritance state of the template being rendered.
+pub(crate) struct TemplateFrame<'a> {
+    /// Overrides of every block, from the most derived template to the base one.
+    pub blocks: BlockChain<'a>,
+    /// Template extended by the one being rendered.
+    parent: RefCell<Option<Rc<Template<'a>>>>,
+    /// Number of scopes entered when the template started rendering.
+    pub depth: usize,
+}
+
+impl<'a> TemplateFrame<'a> {
+    fn new(blocks: BlockChain<'a>, depth: usize) -> Self {
+        Self {
+            blocks,
+            parent: RefCell::new(None),
+            depth,
+        }
+    }
+    pub fn set_parent(&self, parent: Rc<Template<'a>>) -> Result<()> {
+        let mut current_parent = self.parent.borrow_mut();
+        if current_parent.is_some() {
+            return Err(Error::from(RenderErrorKind::ExtendedMultipleTimes));
+        }
+        *current_parent = Some(parent);
+        Ok(())
+    }
+    pub fn is_extended(&self) -> bool {
+        self.parent.borrow().is_some()
+    }
+}
+
+/// Discards the output of a template once it is known to extend another one.
+struct InheritanceWriter<'w, 'a> {
+    out: &'w mut dyn Write,
+    frame: &'w TemplateFrame<'a>,
+}
+
+impl Write for InheritanceWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.frame.is_extended() {
+            Ok(buf.len())
         } else {
-            todo!()
+            self.out.write(buf)
         }
     }
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
 }
@@ -1,7 +1,13 @@
+use crate::context::Context;
 use crate::error::{Error, ParseErrorKind, Result};
-use crate::value::{Value, ValuesMap};
+use crate::filters::FilterFn;
+use crate::globals;
+use crate::stack;
+use crate::testers::TestFn;
+use crate::value::{CallArgs, Callable, Value, ValuesMap};
 use crate::FileSystemHandler;
 use crate::Template;
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -14,8 +20,12 @@ enum Jinja2CompatMode {
 /// Global template environment settings
 #[derive(Clone, Debug, PartialEq)]
 pub struct Settings {
-    /// Enables use of line statements (yet not supported)
+    /// Enables use of line statements and line comments
     pub use_line_statements: bool,
+    /// Prefix of the lines handled as statements, when line statements are enabled
+    pub line_statement_prefix: String,
+    /// Prefix of the comments ending with the line, when line statements are enabled
+    pub line_comment_prefix: String,
     /// Enables blocks trimming the same way as it does python Jinja2 engine
     pub trim_blocks: bool,
     /// Enables blocks stripping (from the left) the same way as it does python Jinja2 engine
@@ -24,8 +34,16 @@ pub struct Settings {
     pub cache_size: usize,
     /// If auto_reload is set to true (default) every time a template is requested the loader checks if the source changed and if yes, it will reload the template
     pub auto_reload: bool,
+    /// Escapes the result of every expression written to the output unless marked safe
+    pub autoescape: bool,
+    /// Handling of undefined values, such as missing variables or out of range indices
+    pub undefined: UndefinedBehavior,
     /// Extensions set enabled for templates
-    extensions: Extensions,
+    pub extensions: Extensions,
+    /// Stack space, in bytes, that parsing an expression or statement, and rendering a template,
+    /// may use before failing with a nesting or recursion error, 768 KiB by default. Raise it
+    /// along with the stack size of the threads rendering deeply nested templates.
+    pub stack_budget: usize,
     /// Controls Jinja2 compatibility mode
     jinja_compat_mode: Jinja2CompatMode,
 }
@@ -34,26 +52,53 @@ impl Default for Settings {
     fn default() -> Settings {
         Settings {
             use_line_statements: false,
+            line_statement_prefix: "#".to_string(),
+            line_comment_prefix: "##".to_string(),
             trim_blocks: false,
             lstrip_blocks: false,
             cache_size: 400,
             auto_reload: true,
+            autoescape: false,
+            undefined: UndefinedBehavior::default(),
             extensions: Extensions::default(),
+            stack_budget: stack::DEFAULT_STACK_BUDGET,
             jinja_compat_mode: Jinja2CompatMode::default(),
         }
     }
 }
 
-/// Extensions set which should be supported
+/// Behavior of templates using a value which is not defined
 #[derive(Clone, Debug, PartialEq, Default)]
-struct Extensions {
+pub enum UndefinedBehavior {
+    /// Fails the render with an error
+    #[default]
+    Strict,
+    /// Evaluates to an undefined value, rendered as an empty string
+    Lenient,
+}
+
+/// Extensions set which should be supported. The i18n extension of Jinja2, with its `trans`
+/// blocks and gettext functions, is not available yet.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Extensions {
     /// Enable use of `do` statement
-    do_ext: bool,
+    pub do_ext: bool,
+    /// Enable use of `break` and `continue` statements in loops. As in Jinja2, included
+    /// templates are parsed on their own, so they can't break out of the including loop.
+    pub loop_controls: bool,
+}
+
+/// Filters and tests registered from Rust code, resolved while templates are parsed
+#[derive(Clone, Default)]
+pub struct Registry {
+    pub filters: HashMap<String, Arc<FilterFn>>,
+    pub tests: HashMap<String, Arc<TestFn>>,
 }
 
 pub struct TemplateEnv<'a> {
     settings: Settings,
     global_values: Arc<RwLock<ValuesMap>>,
+    registry: Arc<Registry>,
     filesystem_handlers: Vec<Box<dyn FileSystemHandler + 'a>>,
 }
 
@@ -65,6 +110,15 @@ impl<'a> TemplateEnv<'a> {
         self.global_values.write().unwrap().insert(name, val.into());
     }
 
+    /// Registers a Rust function callable as `name(args)` from the templates of this environment.
+    pub fn add_function<F>(&mut self, name: String, function: F)
+    where
+        F: Fn(CallArgs, &Context<'_>) -> Result<Value> + Send + Sync + 'static,
+    {
+        let callable = Callable::new(&name, function);
+        self.add_global(name, callable);
+    }
+
     pub fn remove_global(&mut self, name: String) {
         self.global_values.write().unwrap().remove(&name);
     }
@@ -72,6 +126,32 @@ impl<'a> TemplateEnv<'a> {
         self.global_values.clone()
     }
 
+    /// Registers a filter usable as `value|name(args)` in the templates of this environment.
+    /// A filter with the name of a built-in one replaces it.
+    pub fn add_filter<F>(&mut self, name: String, filter: F)
+    where
+        F: Fn(Value, CallArgs, &Context<'_>) -> Result<Value> + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.registry)
+            .filters
+            .insert(name, Arc::new(filter));
+    }
+
+    /// Registers a test usable as `value is name(args)` in the templates of this environment.
+    /// A test with the name of a built-in one replaces it.
+    pub fn add_test<F>(&mut self, name: String, test: F)
+    where
+        F: Fn(Value, CallArgs, &Context<'_>) -> Result<bool> + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.registry)
+            .tests
+            .insert(name, Arc::new(test));
+    }
+
+    pub(crate) fn registry(&self) -> Arc<Registry> {
+        self.registry.clone()
+    }
+
     pub fn set_settings(&mut self, settings: Settings) {
         self.settings = settings;
     }
@@ -118,7 +198,8 @@ impl<'a> Default for TemplateEnv<'a> {
     fn default() -> TemplateEnv<'a> {
         TemplateEnv {
             settings: Settings::default(),
-            global_values: Arc::new(RwLock::new(ValuesMap::default())),
+            global_values: Arc::new(RwLock::new(globals::default_globals())),
+            registry: Arc::default(),
             filesystem_handlers: vec![],
         }
     }
@@ -3,13 +3,15 @@ use std::sync::RwLock;
 
 use crate::error::{Error, ParseError, ParseErrorKind, Result};
 use crate::expression_parser::ExpressionParser;
-use crate::keyword::{RegexEnum, ROUGH_TOKENIZER};
+use crate::keyword::{RegexEnum, LINE_COMMENT_PREFIX, LINE_STATEMENT_PREFIX, ROUGH_TOKENIZER};
+use crate::lexer::Token;
 use crate::renderer::ExpressionRenderer;
 use crate::renderer::{ComposedRenderer, RawTextRenderer};
 use crate::source::{Range, SourceLocation, SourceLocationInfo};
 use crate::statement::parser::StatementParser;
-use crate::statement::{StatementInfo, StatementInfoList, StatementInfoType};
+use crate::statement::{Blocks, StatementInfo, StatementInfoList, StatementInfoType};
 use crate::template_env::TemplateEnv;
+use logos::Logos;
 use regex::Regex;
 
 pub struct TemplateParser<'a> {
@@ -24,7 +26,25 @@ pub struct TemplateParser<'a> {
 
 impl<'a> TemplateParser<'a> {
     pub fn new(body: &'a str, env: &'a TemplateEnv<'_>) -> Result<Self> {
-        let rough_tokenizer = Regex::new(&ROUGH_TOKENIZER[..ROUGH_TOKENIZER.len() - 1]).unwrap();
+        let settings = env.settings();
+        let line_prefix = |prefix: &str| {
+            if settings.use_line_statements && !prefix.is_empty() {
+                regex::escape(prefix)
+            } else {
+                // Never matches, so the line syntax stays disabled.
+                r"[^\s\S]".to_string()
+            }
+        };
+        let rough_tokenizer = ROUGH_TOKENIZER[..ROUGH_TOKENIZER.len() - 1]
+            .replace(
+                LINE_COMMENT_PREFIX,
+                &line_prefix(&settings.line_comment_prefix),
+            )
+            .replace(
+                LINE_STATEMENT_PREFIX,
+                &line_prefix(&settings.line_statement_prefix),
+            );
+        let rough_tokenizer = Regex::new(&rough_tokenizer).unwrap();
 
         Ok(Self {
             template_body: body,
@@ -48,7 +68,9 @@ impl<'a> TemplateParser<'a> {
     }
     fn parse_expression(&self, range: Range) -> Result<ExpressionRenderer<'a>> {
         let text = self.template_body;
-        let expression = ExpressionParser::parse(&text[range.span()]);
+        let env = self.env.read().unwrap();
+        let expression =
+            ExpressionParser::parse(&text[range.span()], env.settings(), env.registry());
         if let Err(Error::ParseError(mut parse_error)) = expression {
             let new_source = self.update_location(&parse_error.location, range);
             parse_error.set_location(new_source);
@@ -63,7 +85,13 @@ impl<'a> TemplateParser<'a> {
         statements_stack: &mut StatementInfoList<'a>,
     ) -> Result<()> {
         let text = self.template_body;
-        let statement = StatementParser::parse(&text[range.span()], statements_stack);
+        let env = self.env.read().unwrap();
+        let statement = StatementParser::parse(
+            &text[range.span()],
+            statements_stack,
+            env.settings(),
+            env.registry(),
+        );
         if let Err(Error::ParseError(mut parse_error)) = statement {
             let new_source = self.update_location(&parse_error.location, range);
             parse_error.set_location(new_source);
@@ -72,7 +100,21 @@ impl<'a> TemplateParser<'a> {
             statement
         }
     }
-    fn fine_parsing(&self, renderer: Rc<ComposedRenderer<'a>>) -> Result<()> {
+    /// Lets the innermost macro take extra positional arguments when the block refers to
+    /// `varargs`, as Jinja2 decides from the macro body.
+    fn find_varargs(&self, range: Range, statements_stack: &StatementInfoList<'a>) {
+        let text = &self.template_body[range.span()];
+        let registry = self.env.read().unwrap().registry();
+        let uses_varargs = Token::lexer_with_extras(text, registry)
+            .any(|token| matches!(token, Ok(Token::Identifier(name)) if name == "varargs"));
+        if uses_varargs {
+            statements_stack
+                .iter()
+                .rev()
+                .any(|info| info.catch_varargs());
+        }
+    }
+    fn fine_parsing(&self, renderer: Rc<ComposedRenderer<'a>>) -> Result<Blocks<'a>> {
         let mut statements_stack: StatementInfoList<'_> = vec![];
         let root = StatementInfo::new(StatementInfoType::TemplateRoot, None, renderer);
         statements_stack.push(root);
@@ -91,6 +133,7 @@ impl<'a> TemplateParser<'a> {
                         .add_renderer(Box::new(new_renderer));
                 }
                 TextBlockType::Expression => {
+                    self.find_varargs(orig_block.range, &statements_stack);
                     let new_renderer = self.parse_expression(orig_block.range)?;
 
                     statements_stack
@@ -99,21 +142,41 @@ impl<'a> TemplateParser<'a> {
                         .current_composition
                         .add_renderer(Box::new(new_renderer));
                 }
-                TextBlockType::Comment => {}
+                TextBlockType::Comment | TextBlockType::LineComment => {}
                 TextBlockType::Statement | TextBlockType::LineStatement => {
+                    self.find_varargs(orig_block.range, &statements_stack);
+                    let depth = statements_stack.len();
                     self.parse_statement(orig_block.range, &mut statements_stack)?;
+                    if statements_stack.len() > depth {
+                        if let Some(info) = statements_stack.last_mut() {
+                            info.position = Some(orig_block.range.start);
+                        }
+                    }
                 }
             }
         }
-        Ok(())
+        let unclosed = statements_stack
+            .iter()
+            .rev()
+            .find_map(|info| info.end_tag().zip(info.position));
+        if let Some((end_tag, position)) = unclosed {
+            return Err(Error::from(ParseError::new(
+                ParseErrorKind::UnclosedStatement(end_tag),
+                Some(self.make_source_location(position)),
+            )));
+        }
+        Ok(statements_stack.remove(0).blocks)
     }
 
-    pub fn parse(&mut self) -> Result<ComposedRenderer<'a>> {
+    pub fn parse(&mut self) -> Result<(ComposedRenderer<'a>, Blocks<'a>)> {
         match self.rough_parsing() {
             Ok(_) => {
                 let renderer = Rc::new(ComposedRenderer::new());
-                self.fine_parsing(renderer.clone())?;
-                Ok(Rc::try_unwrap(renderer).unwrap())
+                let blocks = self.fine_parsing(renderer.clone())?;
+                match Rc::try_unwrap(renderer) {
+                    Ok(renderer) => Ok((renderer, blocks)),
+                    Err(_) => Err(Error::from(ParseErrorKind::Unspecified)),
+                }
             }
             Err(error) => Err(error),
         }
@@ -128,7 +191,7 @@ impl<'a> TemplateParser<'a> {
             let mut n_regex = 0;
             let mut match_end = 0;
 
-            for i in 1..10 {
+            for i in 1..capture.len() {
                 if let Some(m) = capture.get(i) {
                     n_regex = i - 1;
                     match_start = m.start();
@@ -144,20 +207,29 @@ impl<'a> TemplateParser<'a> {
                     let new_line_start = self.current_line_info.read().unwrap().range.end + 1;
                     self.current_line_info.write().unwrap().range.start = new_line_start;
 
-                    if self.current_line_info.read().unwrap().range.start < self.template_body.len()
-                    {
-                        match self.current_block_info.read().unwrap().mode {
-                            TextBlockType::LineStatement => {}
-                            _ => continue,
-                        };
-                        self.finish_current_block(match_start, TextBlockType::RawText, None);
-                        self.current_block_info.write().unwrap().range.start =
-                            self.current_line_info.read().unwrap().range.start;
-                    }
+                    match self.current_block_info.read().unwrap().mode {
+                        TextBlockType::LineStatement | TextBlockType::LineComment => {}
+                        _ => continue,
+                    };
+                    self.finish_line_block(match_start);
+                }
+                RegexEnum::LineStmtBegin => {
+                    match self.current_block_info.read().unwrap().mode {
+                        TextBlockType::RawText => {}
+                        _ => continue,
+                    };
+                    self.start_line_block(TextBlockType::LineStatement, match_start, match_end);
+                }
+                RegexEnum::LineCommentBegin => {
+                    match self.current_block_info.read().unwrap().mode {
+                        TextBlockType::RawText => {}
+                        _ => continue,
+                    };
+                    self.start_line_block(TextBlockType::LineComment, match_start, match_end);
                 }
                 RegexEnum::CommentBegin => {
                     match self.current_block_info.read().unwrap().mode {
-                        TextBlockType::RawBlock => continue,
+                        TextBlockType::RawBlock | TextBlockType::LineComment => continue,
                         TextBlockType::RawText => {}
                         _ => {
                             self.finish_current_line(match_end);
@@ -175,7 +247,7 @@ impl<'a> TemplateParser<'a> {
 
                 RegexEnum::CommentEnd => {
                     match self.current_block_info.read().unwrap().mode {
-                        TextBlockType::RawBlock => continue,
+                        TextBlockType::RawBlock | TextBlockType::LineComment => continue,
                         TextBlockType::Comment => {}
                         _ => {
                             self.finish_current_line(match_end);
@@ -231,7 +303,7 @@ impl<'a> TemplateParser<'a> {
                 }
                 RegexEnum::RawBegin => {
                     match self.current_block_info.read().unwrap().mode {
-                        TextBlockType::RawBlock => continue,
+                        TextBlockType::RawBlock | TextBlockType::LineComment => continue,
                         TextBlockType::Comment | TextBlockType::RawText => {}
                         _ => {
                             self.finish_current_line(match_end);
@@ -245,7 +317,7 @@ impl<'a> TemplateParser<'a> {
                 }
                 RegexEnum::RawEnd => {
                     match self.current_block_info.read().unwrap().mode {
-                        TextBlockType::Comment => continue,
+                        TextBlockType::Comment | TextBlockType::LineComment => continue,
                         TextBlockType::RawBlock => {}
                         _ => {
                             self.finish_current_line(match_end);
@@ -272,11 +344,50 @@ impl<'a> TemplateParser<'a> {
                 Some(self.make_source_location(len_of_temp)), // TODO: THERE is not handling of expected end of comment????
             )));
         }
-        self.finish_current_block(len_of_temp, TextBlockType::RawText, None);
+        let mode = self.current_block_info.read().unwrap().mode;
+        match mode {
+            TextBlockType::LineStatement | TextBlockType::LineComment => {
+                self.finish_line_block(len_of_temp)
+            }
+            _ => {
+                self.finish_current_block(len_of_temp, TextBlockType::RawText, None);
+            }
+        }
 
         Ok(())
     }
 
+    fn start_line_block(&self, mode: TextBlockType, match_start: usize, match_end: usize) {
+        self.current_block_info.write().unwrap().range.end = match_start;
+        self.text_blocks
+            .write()
+            .unwrap()
+            .push(*self.current_block_info.read().unwrap());
+
+        let mut current_block = self.current_block_info.write().unwrap();
+        current_block.range.start = match_end;
+        current_block.mode = mode;
+    }
+    /// Ends the line statement or comment being parsed at the given line end.
+    fn finish_line_block(&self, position: usize) {
+        let mut current_block = self.current_block_info.write().unwrap();
+        let mut end = position;
+        if let TextBlockType::LineStatement = current_block.mode {
+            // Line statements may end with a colon, as in `# for item in items:`
+            let line = self.template_body[current_block.range.start..position].trim_end();
+            end = current_block.range.start + line.strip_suffix(':').unwrap_or(line).len();
+        }
+        current_block.range.end = end;
+        self.text_blocks.write().unwrap().push(*current_block);
+
+        // The end of line belongs to the line statement, but not to the comment.
+        current_block.range.start = match current_block.mode {
+            TextBlockType::LineStatement => (position + 1).min(self.template_body.len()),
+            _ => position,
+        };
+        current_block.mode = TextBlockType::RawText;
+    }
+
     fn start_control_block(
         &self,
         mode: TextBlockType,
@@ -293,12 +404,8 @@ impl<'a> TemplateParser<'a> {
         };
         self.finish_current_block(end_offset, mode, None);
 
-        if start_offset < self.template_body.len() {
-            let ctrl_char = self.template_body.chars().nth(start_offset).unwrap();
-
-            if ctrl_char == '+' || ctrl_char == '-' {
-                start_offset += 1;
-            }
+        if let Some('+' | '-') = self.char_at(start_offset) {
+            start_offset += 1;
         }
         //m_currentBlockInfo.type = blockType; TODO: remove line in jinja2cpp
         if let TextBlockType::RawBlock = self.current_block_info.read().unwrap().mode {
@@ -345,10 +452,11 @@ impl<'a> TemplateParser<'a> {
                     );
                 }
 
-                if position != 0 {
-                    let ctrl_char = self.template_body.chars().nth(position - 1).unwrap();
-
-                    if ctrl_char == '+' || ctrl_char == '-' {
+                // The control character of an unterminated block, as in `{{-`, is not
+                // part of the block.
+                let start = self.current_block_info.read().unwrap().range.start;
+                if position > start {
+                    if let Some('+' | '-') = self.char_at(position - 1) {
                         position -= 1;
                     }
                 }
@@ -373,8 +481,7 @@ impl<'a> TemplateParser<'a> {
         mut do_trim: bool,
     ) -> usize {
         let mut do_total_strip = false;
-        if ctrl_char_pos < self.template_body.len() {
-            let ctrl_char = self.template_body.chars().nth(ctrl_char_pos).unwrap();
+        if let Some(ctrl_char) = self.char_at(ctrl_char_pos) {
             if ctrl_char == '+' {
                 do_trim = false;
             } else {
@@ -411,7 +518,7 @@ impl<'a> TemplateParser<'a> {
                 }
                 same_line = false;
             }
-            end_offset -= 1;
+            end_offset -= ch.len_utf8();
         }
 
         end_offset
@@ -423,13 +530,10 @@ impl<'a> TemplateParser<'a> {
             match self.current_block_info.read().unwrap().mode {
                 TextBlockType::RawText => {}
                 _ => {
-                    let ctrl_char = self.template_body.chars().nth(position - 1).unwrap();
-                    do_trim = if ctrl_char == '-' {
-                        true
-                    } else if ctrl_char == '+' {
-                        false
-                    } else {
-                        do_trim
+                    do_trim = match self.char_at(position - 1) {
+                        Some('-') => true,
+                        Some('+') => false,
+                        _ => do_trim,
                     }
                 }
             }
@@ -438,7 +542,7 @@ impl<'a> TemplateParser<'a> {
         if do_trim {
             let templ = &self.template_body;
 
-            for ch in templ[position + 2..].chars() {
+            for ch in templ.get(position + 2..).unwrap_or_default().chars() {
                 if ch == '\n' {
                     new_pos += 1;
                     break;
@@ -446,12 +550,18 @@ impl<'a> TemplateParser<'a> {
                 if !ch.is_whitespace() {
                     break;
                 }
-                new_pos += 1;
+                new_pos += ch.len_utf8();
             }
         }
 
         new_pos
     }
+    /// Returns the character starting at the byte offset `position`, if any.
+    fn char_at(&self, position: usize) -> Option<char> {
+        self.template_body
+            .get(position..)
+            .and_then(|rest| rest.chars().next())
+    }
     fn finish_current_line(&self, position: usize) {
         self.current_line_info.write().unwrap().range.end = position;
         self.lines
@@ -463,16 +573,17 @@ impl<'a> TemplateParser<'a> {
 
     fn make_source_location(&self, match_begin: usize) -> SourceLocationInfo {
         for line in self.lines.read().unwrap().iter() {
-            if line.range.start >= match_begin && line.range.end < match_begin {
+            if line.range.start <= match_begin && match_begin <= line.range.end {
                 let col = match_begin - line.range.start;
 
-                let line_number = line.line_number;
+                // Lines are numbered from 1 in messages
+                let line_number = line.line_number + 1;
                 return SourceLocationInfo::new(line_number, col);
             }
         }
         let line = self.current_line_info.read().unwrap();
         let line_number = line.line_number;
-        let col = match_begin - line.range.start;
+        let col = match_begin.saturating_sub(line.range.start);
         SourceLocationInfo::new(line_number, col)
     }
 }
@@ -497,8 +608,8 @@ enum TextBlockType {
     Expression,
     Statement,
     Comment,
-    #[allow(dead_code)]
     LineStatement,
+    LineComment,
     RawBlock,
 }
 
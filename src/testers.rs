@@ -0,0 +1,208 @@
+use crate::context::Context;
+use crate::error::{Error, ParseError, ParseErrorKind, RenderErrorKind, Result};
+use crate::expression_evaluator::CallParams;
+use crate::template_env::Registry;
+use crate::value::{CallArgs, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub type TestFn = dyn Fn(Value, CallArgs, &Context<'_>) -> Result<bool> + Send + Sync;
+
+pub enum Tester {
+    Boolean,
+    Defined,
+    DivisibleBy,
+    Eq,
+    Even,
+    Ge,
+    Gt,
+    In,
+    Iterable,
+    Le,
+    Lower,
+    Lt,
+    Mapping,
+    Ne,
+    None,
+    Number,
+    Odd,
+    SameAs,
+    Sequence,
+    String,
+    Undefined,
+    Upper,
+    Custom(Arc<TestFn>),
+}
+const BUILTIN_TESTS: &[&str] = &[
+    "boolean",
+    "defined",
+    "divisibleby",
+    "eq",
+    "equalto",
+    "even",
+    "ge",
+    "gt",
+    "greaterthan",
+    "in",
+    "iterable",
+    "le",
+    "lower",
+    "lt",
+    "lessthan",
+    "mapping",
+    "ne",
+    "none",
+    "number",
+    "odd",
+    "sameas",
+    "sequence",
+    "string",
+    "undefined",
+    "upper",
+];
+
+impl Tester {
+    pub fn new(name: &str, registry: &Registry) -> Result<Self> {
+        if let Some(test) = registry.tests.get(name) {
+            return Ok(Tester::Custom(test.clone()));
+        }
+        match name {
+            "boolean" => Ok(Tester::Boolean),
+            "defined" => Ok(Tester::Defined),
+            "divisibleby" => Ok(Tester::DivisibleBy),
+            "eq" | "equalto" => Ok(Tester::Eq),
+            "even" => Ok(Tester::Even),
+            "ge" => Ok(Tester::Ge),
+            "gt" | "greaterthan" => Ok(Tester::Gt),
+            "in" => Ok(Tester::In),
+            "iterable" => Ok(Tester::Iterable),
+            "le" => Ok(Tester::Le),
+            "lower" => Ok(Tester::Lower),
+            "lt" | "lessthan" => Ok(Tester::Lt),
+            "mapping" => Ok(Tester::Mapping),
+            "ne" => Ok(Tester::Ne),
+            "none" => Ok(Tester::None),
+            "number" => Ok(Tester::Number),
+            "odd" => Ok(Tester::Odd),
+            "sameas" => Ok(Tester::SameAs),
+            "sequence" => Ok(Tester::Sequence),
+            "string" => Ok(Tester::String),
+            "undefined" => Ok(Tester::Undefined),
+            "upper" => Ok(Tester::Upper),
+            unknown => {
+                let known = BUILTIN_TESTS
+                    .iter()
+                    .copied()
+                    .chain(registry.tests.keys().map(String::as_str));
+                Err(Error::from(ParseError::new(
+                    ParseErrorKind::UnknownTest(unknown.to_string(), closest_name(unknown, known)),
+                    None,
+                )))
+            }
+        }
+    }
+    pub fn test(
+        &self,
+        value: Value,
+        params: &Option<CallParams<'_>>,
+        context: Context<'_>,
+    ) -> Result<bool> {
+        match &self {
+            Tester::Boolean => Ok(matches!(value, Value::Boolean(_))),
+            Tester::Defined => Ok(!matches!(value, Value::Empty)),
+            Tester::DivisibleBy => {
+                let num = Tester::argument(params, "num", context)?;
+                value.is_divisible_by(&num)
+            }
+            Tester::Eq => Ok(value == Tester::argument(params, "other", context)?),
+            Tester::Even => Ok(value.has_parity(0)),
+            Tester::Ge => Ok(value >= Tester::argument(params, "other", context)?),
+            Tester::Gt => Ok(value > Tester::argument(params, "other", context)?),
+            Tester::In => Tester::argument(params, "seq", context)?.contains(&value),
+            Tester::Iterable | Tester::Sequence => Ok(value.is_iterable()),
+            Tester::Le => Ok(value <= Tester::argument(params, "other", context)?),
+            Tester::Lower => Ok(value.is_lower()),
+            Tester::Lt => Ok(value < Tester::argument(params, "other", context)?),
+            Tester::Mapping => Ok(matches!(value, Value::ValuesMap(_))),
+            Tester::Ne => Ok(value != Tester::argument(params, "other", context)?),
+            Tester::None => Ok(matches!(value, Value::None)),
+            Tester::Number => Ok(matches!(value, Value::Integer(_) | Value::Double(_))),
+            Tester::Odd => Ok(value.has_parity(1)),
+            Tester::SameAs => Ok(value.is_same_as(&Tester::argument(params, "other", context)?)),
+            Tester::String => Ok(matches!(value, Value::String(_) | Value::SafeString(_))),
+            Tester::Undefined => Ok(matches!(value, Value::Empty)),
+            Tester::Upper => Ok(value.is_upper()),
+            Tester::Custom(test) => {
+                let args = match params {
+                    Some(params) => params.evaluate(context.clone())?,
+                    None => CallArgs::default(),
+                };
+                test(value, args, &context)
+            }
+        }
+    }
+    fn argument(
+        params: &Option<CallParams<'_>>,
+        name: &str,
+        context: Context<'_>,
+    ) -> Result<Value> {
+        let mut parameters = match params {
+            Some(params) => params.parse(vec![name], context)?,
+            None => HashMap::default(),
+        };
+        parameters
+            .remove(name)
+            .ok_or_else(|| Error::from(RenderErrorKind::MissingArgument(name.to_string())))
+    }
+}
+
+pub struct TesterExpression<'a> {
+    tester: Tester,
+    params: Option<CallParams<'a>>,
+    negated: bool,
+}
+
+impl<'a> TesterExpression<'a> {
+    pub fn new(
+        identifier: &str,
+        params: Option<CallParams<'a>>,
+        negated: bool,
+        registry: &Registry,
+    ) -> Result<Self> {
+        let tester = Tester::new(identifier, registry)?;
+        Ok(Self {
+            tester,
+            params,
+            negated,
+        })
+    }
+
+    pub fn test(&self, value: Value, context: Context<'_>) -> Result<bool> {
+        let result = self.tester.test(value, &self.params, context)?;
+        Ok(result != self.negated)
+    }
+}
+
+/// Returns the known name closest to `name`, if any is a likely misspelling of it.
+fn closest_name<'b>(name: &str, known: impl Iterator<Item = &'b str>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    known
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+    for (i, left_char) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, right_char) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(left_char != *right_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[right.len()]
+}
@@ -0,0 +1,90 @@
+use super::{Value, ValuesList, ValuesMap};
+use crate::context::Context;
+use crate::error::{Error, RenderErrorKind, Result};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+type CallableFn = dyn Fn(CallArgs, &Context<'_>) -> Result<Value> + Send + Sync;
+
+/// Arguments already evaluated at the call site.
+#[derive(Clone, Debug, Default)]
+pub struct CallArgs {
+    pub positional: ValuesList,
+    pub keyword: ValuesMap,
+}
+
+impl CallArgs {
+    /// Returns the argument passed as `name`, or else at position `index`, converted to `T`.
+    pub fn get<T>(&self, index: usize, name: &str) -> Result<T>
+    where
+        T: TryFrom<Value>,
+        Error: From<T::Error>,
+    {
+        self.get_optional(index, name)?
+            .ok_or_else(|| Error::RenderError(RenderErrorKind::MissingArgument(name.to_string())))
+    }
+    /// Same as `get`, but gives `None` when the argument was not passed.
+    pub fn get_optional<T>(&self, index: usize, name: &str) -> Result<Option<T>>
+    where
+        T: TryFrom<Value>,
+        Error: From<T::Error>,
+    {
+        match self
+            .keyword
+            .get(name)
+            .or_else(|| self.positional.get(index))
+        {
+            Some(value) => Ok(Some(T::try_from(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A value that can be invoked from a template expression. It may also carry attributes, as the
+/// `loop` of a recursive for loop does.
+#[derive(Clone)]
+pub struct Callable {
+    name: String,
+    function: Arc<CallableFn>,
+    attributes: ValuesMap,
+}
+
+impl Callable {
+    pub fn new<F>(name: &str, function: F) -> Self
+    where
+        F: Fn(CallArgs, &Context<'_>) -> Result<Value> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            function: Arc::new(function),
+            attributes: ValuesMap::default(),
+        }
+    }
+    /// Gives the callable the attributes reachable with `callable.name` in templates.
+    pub fn with_attributes(mut self, attributes: ValuesMap) -> Self {
+        self.attributes = attributes;
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn attribute(&self, name: &str) -> Option<&Value> {
+        self.attributes.get(name)
+    }
+    pub fn call(&self, args: CallArgs, context: &Context<'_>) -> Result<Value> {
+        (self.function)(args, context)
+    }
+}
+
+impl fmt::Debug for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Callable({})", self.name)
+    }
+}
+
+impl fmt::Display for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<callable '{}'>", self.name)
+    }
+}
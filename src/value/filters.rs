@@ -1,31 +1,39 @@
-use super::Value;
+use super::{check_length, Value};
 use crate::error::{Error, RenderErrorKind, Result};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use regex::Regex;
 
 impl Value {
     pub fn abs(self) -> Result<Self> {
         match self {
-            Value::Integer(number) => Ok(Value::Integer(number.abs())),
+            Value::Integer(number) => number
+                .checked_abs()
+                .map(Value::Integer)
+                .ok_or_else(|| Error::from(RenderErrorKind::InvalidOperation)),
             Value::Double(number) => Ok(Value::Double(number.abs())),
             _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
         }
     }
     pub fn capitalize(self) -> Result<Self> {
         match self {
-            Value::String(s) => Ok(Value::String(format!(
-                "{}{}",
-                &s[0..1].to_string().to_uppercase(),
-                &s[1..],
-            ))),
+            Value::String(s) => {
+                let mut chars = s.chars();
+                let capitalized = match chars.next() {
+                    Some(first) => format!("{}{}", first.to_uppercase(), chars.as_str()),
+                    None => s,
+                };
+                Ok(Value::String(capitalized))
+            }
             _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
         }
     }
     pub fn center(self, mut params: HashMap<&str, Value>) -> Result<Self> {
         let string_value = self.to_string();
         let width = params.remove("width").unwrap_or(Value::Integer(80));
-        let width = width.int(HashMap::default())? as usize;
+        let width = usize::try_from(width.int(HashMap::default())?).unwrap_or(0);
+        check_length(width)?;
         let string_length = string_value.len();
         if string_length > width {
             Ok(self)
@@ -33,7 +41,7 @@ impl Value {
             let whitespaces = width - string_length;
             let result = format!(
                 "{}{}{}",
-                " ".repeat((whitespaces + 1) / 2),
+                " ".repeat(whitespaces.div_ceil(2)),
                 string_value,
                 " ".repeat(whitespaces / 2)
             );
@@ -52,48 +60,58 @@ impl Value {
             value => Ok(value),
         }
     }
+    /// Escapes the value unless it is already marked safe.
     pub fn escape(self) -> Result<Self> {
-        if let Value::String(s) = self {
-            lazy_static! {
-                static ref ESCAPED_REGEX: Regex = Regex::new("[<>&\"\']").unwrap();
-            }
-            let s_input = &s[..];
-            if ESCAPED_REGEX.is_match(s_input) {
-                let mut last_match = 0;
-                let matches = ESCAPED_REGEX.find_iter(s_input);
-                let mut output = String::with_capacity(s_input.len());
-                for mat in matches {
-                    output.push_str(&s_input[last_match..mat.start()]);
-                    match &s_input[mat.range()] {
-                        "<" => output.push_str("&lt;"),
-                        ">" => output.push_str("&gt;"),
-                        "&" => output.push_str("&amp;"),
-                        "\"" => output.push_str("&#34;"),
-                        "\'" => output.push_str("&#39;"),
-                        _ => unreachable!(),
-                    }
-                    last_match = mat.end();
+        match self {
+            Value::SafeString(_) => Ok(self),
+            value => value.force_escape(),
+        }
+    }
+    /// Escapes the value even if it is marked safe.
+    pub fn force_escape(self) -> Result<Self> {
+        lazy_static! {
+            static ref ESCAPED_REGEX: Regex = Regex::new("[<>&\"\']").unwrap();
+        }
+        let s = self.to_string();
+        let s_input = &s[..];
+        if ESCAPED_REGEX.is_match(s_input) {
+            let mut last_match = 0;
+            let matches = ESCAPED_REGEX.find_iter(s_input);
+            let mut output = String::with_capacity(s_input.len());
+            for mat in matches {
+                output.push_str(&s_input[last_match..mat.start()]);
+                match &s_input[mat.range()] {
+                    "<" => output.push_str("&lt;"),
+                    ">" => output.push_str("&gt;"),
+                    "&" => output.push_str("&amp;"),
+                    "\"" => output.push_str("&#34;"),
+                    "\'" => output.push_str("&#39;"),
+                    _ => unreachable!(),
                 }
-                Ok(Value::String(output))
-            } else {
-                Ok(Value::String(s))
+                last_match = mat.end();
             }
+            output.push_str(&s_input[last_match..]);
+            Ok(Value::SafeString(output))
         } else {
-            Err(Error::from(RenderErrorKind::InvalidOperation))
+            Ok(Value::SafeString(s))
+        }
+    }
+    pub fn safe(self) -> Result<Self> {
+        match self {
+            Value::SafeString(_) => Ok(self),
+            value => Ok(Value::SafeString(value.to_string())),
         }
     }
 
     pub fn first(self) -> Result<Self> {
-        if self.is_empty()? {
-            Ok(Value::Empty)
-        } else {
-            match self {
-                Value::String(s) => Ok(Value::String(s.chars().next().unwrap().to_string())),
-                Value::ValuesList(values_list) => Ok(values_list.first().unwrap().clone()),
-                Value::ValuesMap(values_map) => Ok(values_map.values().next().unwrap().clone()),
-                _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
-            }
-        }
+        let item = match self {
+            Value::Empty => None,
+            Value::String(s) => s.chars().next().map(|c| Value::String(c.to_string())),
+            Value::ValuesList(values_list) => values_list.into_iter().next(),
+            Value::ValuesMap(values_map) => values_map.into_values().next(),
+            _ => return Err(Error::from(RenderErrorKind::InvalidOperation)),
+        };
+        Ok(item.unwrap_or(Value::Empty))
     }
     pub fn float(self, mut params: HashMap<&str, Value>) -> Result<f64> {
         match self {
@@ -141,42 +159,36 @@ impl Value {
         }
     }
     pub fn max(self) -> Result<Self> {
-        if self.is_empty()? {
-            Ok(Value::Empty)
-        } else {
-            match self {
-                Value::String(s) => Ok(Value::String(s.chars().max().unwrap().to_string())),
-                Value::ValuesList(values_list) => Ok(values_list.iter().max().unwrap().clone()),
-                Value::ValuesMap(values_map) => Ok(values_map.values().last().unwrap().clone()),
-                _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
-            }
-        }
+        let item = match self {
+            Value::Empty => None,
+            Value::String(s) => s.chars().max().map(|c| Value::String(c.to_string())),
+            Value::ValuesList(values_list) => values_list.into_iter().max(),
+            Value::ValuesMap(values_map) => values_map.into_values().last(),
+            _ => return Err(Error::from(RenderErrorKind::InvalidOperation)),
+        };
+        Ok(item.unwrap_or(Value::Empty))
     }
 
     pub fn min(self) -> Result<Self> {
-        if self.is_empty()? {
-            Ok(Value::Empty)
-        } else {
-            match self {
-                Value::String(s) => Ok(Value::String(s.chars().min().unwrap().to_string())),
-                Value::ValuesList(values_list) => Ok(values_list.iter().min().unwrap().clone()),
-                Value::ValuesMap(values_map) => Ok(values_map.values().next().unwrap().clone()),
-                _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
-            }
-        }
+        let item = match self {
+            Value::Empty => None,
+            Value::String(s) => s.chars().min().map(|c| Value::String(c.to_string())),
+            Value::ValuesList(values_list) => values_list.into_iter().min(),
+            Value::ValuesMap(values_map) => values_map.into_values().next(),
+            _ => return Err(Error::from(RenderErrorKind::InvalidOperation)),
+        };
+        Ok(item.unwrap_or(Value::Empty))
     }
 
     pub fn last(self) -> Result<Self> {
-        if self.is_empty()? {
-            Ok(Value::Empty)
-        } else {
-            match self {
-                Value::String(s) => Ok(Value::String(s.chars().last().unwrap().to_string())),
-                Value::ValuesList(values_list) => Ok(values_list.last().unwrap().clone()),
-                Value::ValuesMap(values_map) => Ok(values_map.values().last().unwrap().clone()),
-                _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
-            }
-        }
+        let item = match self {
+            Value::Empty => None,
+            Value::String(s) => s.chars().last().map(|c| Value::String(c.to_string())),
+            Value::ValuesList(values_list) => values_list.into_iter().last(),
+            Value::ValuesMap(values_map) => values_map.into_values().last(),
+            _ => return Err(Error::from(RenderErrorKind::InvalidOperation)),
+        };
+        Ok(item.unwrap_or(Value::Empty))
     }
     #[allow(clippy::len_without_is_empty)]
     pub fn len(self) -> Result<usize> {
@@ -220,10 +232,10 @@ impl Value {
     }
     pub fn sum(self) -> Result<Self> {
         if let Value::ValuesList(values_list) = self {
-            let value: f64 = values_list
-                .iter()
-                .map(|value| value.clone().float(HashMap::default()).unwrap())
-                .sum();
+            let mut value = 0.0;
+            for item in values_list {
+                value += item.float(HashMap::default())?;
+            }
             Ok(Value::Double(value))
         } else {
             Err(Error::from(RenderErrorKind::InvalidOperation))
@@ -262,7 +274,11 @@ impl Value {
         let end = end.to_string();
 
         let value = if string_value.len() > length {
-            string_value.truncate(length - end.len());
+            let mut cut = length.saturating_sub(end.len());
+            while !string_value.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            string_value.truncate(cut);
             string_value.push_str(&end);
             string_value
         } else {
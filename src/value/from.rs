@@ -1,4 +1,6 @@
-use super::{Value, ValuesList, ValuesMap};
+use super::{Callable, Value, ValuesList, ValuesMap};
+use crate::error::{Error, RenderErrorKind};
+use std::convert::TryFrom;
 
 impl From<isize> for Value {
     fn from(f: isize) -> Self {
@@ -104,6 +106,12 @@ impl From<ValuesList> for Value {
     }
 }
 
+impl From<Callable> for Value {
+    fn from(f: Callable) -> Self {
+        Value::Callable(f)
+    }
+}
+
 impl From<Value> for ValuesList {
     fn from(f: Value) -> Self {
         match f {
@@ -112,7 +120,7 @@ impl From<Value> for ValuesList {
                 .keys()
                 .map(|key| Value::String(key.to_string()))
                 .collect(),
-            Value::String(value_string) => value_string
+            Value::String(value_string) | Value::SafeString(value_string) => value_string
                 .chars()
                 .map(|ch| Value::String(ch.to_string()))
                 .collect(),
@@ -120,3 +128,54 @@ impl From<Value> for ValuesList {
         }
     }
 }
+
+impl TryFrom<Value> for i64 {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Integer(i) => Ok(i),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Double(f) => Ok(f),
+            Value::Integer(i) => Ok(i as f64),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) | Value::SafeString(s) => Ok(s),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+}
+
+impl TryFrom<Value> for ValuesMap {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::ValuesMap(map) => Ok(map),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+}
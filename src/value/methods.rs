@@ -0,0 +1,355 @@
+use super::{check_length, CallArgs, Value, ValuesList};
+use crate::error::{Error, RenderErrorKind, Result};
+use std::convert::TryFrom;
+
+impl Value {
+    /// Tells whether `name` is one of the Python methods of the value, such as `items` for dicts.
+    /// As in Jinja2, methods take precedence over dict keys, which remain reachable with `d['key']`.
+    pub fn has_method(&self, name: &str) -> bool {
+        match self {
+            Value::String(_) | Value::SafeString(_) => matches!(
+                name,
+                "upper"
+                    | "lower"
+                    | "strip"
+                    | "startswith"
+                    | "endswith"
+                    | "split"
+                    | "replace"
+                    | "format"
+                    | "join"
+            ),
+            Value::ValuesMap(_) => matches!(name, "items" | "keys" | "values" | "get"),
+            Value::ValuesList(_) => matches!(name, "index" | "count" | "append" | "extend"),
+            _ => false,
+        }
+    }
+    /// Tells whether the method `name` modifies the value it is called on.
+    pub fn is_modifying_method(&self, name: &str) -> bool {
+        matches!((self, name), (Value::ValuesList(_), "append" | "extend"))
+    }
+    /// Calls the Python method `name` of the value. `append` and `extend` modify the list in place.
+    pub fn call_method(&mut self, name: &str, args: CallArgs) -> Result<Value> {
+        match (self, name) {
+            (Value::String(s) | Value::SafeString(s), _) => string_method(s, name, args),
+            (Value::ValuesMap(map), "items") => Ok(Value::ValuesList(
+                map.iter()
+                    .map(|(key, value)| {
+                        Value::ValuesList(vec![Value::String(key.clone()), value.clone()])
+                    })
+                    .collect(),
+            )),
+            (Value::ValuesMap(map), "keys") => Ok(Value::ValuesList(
+                map.keys().map(|key| Value::String(key.clone())).collect(),
+            )),
+            (Value::ValuesMap(map), "values") => {
+                Ok(Value::ValuesList(map.values().cloned().collect()))
+            }
+            (Value::ValuesMap(map), "get") => {
+                let key: String = args.get(0, "key")?;
+                let default = args.get_optional(1, "default")?.unwrap_or(Value::None);
+                Ok(map.get(&key).cloned().unwrap_or(default))
+            }
+            (Value::ValuesList(list), "index") => {
+                let item: Value = args.get(0, "value")?;
+                list.iter()
+                    .position(|value| *value == item)
+                    .map(Value::from)
+                    .ok_or_else(|| Error::from(RenderErrorKind::InvalidOperation))
+            }
+            (Value::ValuesList(list), "count") => {
+                let item: Value = args.get(0, "value")?;
+                Ok(Value::from(
+                    list.iter().filter(|value| **value == item).count(),
+                ))
+            }
+            (Value::ValuesList(list), "append") => {
+                list.push(args.get(0, "object")?);
+                Ok(Value::None)
+            }
+            (Value::ValuesList(list), "extend") => {
+                let items: ValuesList = args.get(0, "iterable")?;
+                list.extend(items);
+                Ok(Value::None)
+            }
+            _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
+        }
+    }
+}
+
+fn string_method(s: &str, name: &str, args: CallArgs) -> Result<Value> {
+    match name {
+        "upper" => Ok(Value::String(s.to_uppercase())),
+        "lower" => Ok(Value::String(s.to_lowercase())),
+        "strip" => match args.get_optional::<String>(0, "chars")? {
+            Some(chars) => Ok(Value::String(
+                s.trim_matches(|c| chars.contains(c)).to_string(),
+            )),
+            None => Ok(Value::String(s.trim().to_string())),
+        },
+        "startswith" | "endswith" => {
+            let affixes = match args.get::<Value>(0, "prefix")? {
+                Value::ValuesList(affixes) => affixes,
+                affix => vec![affix],
+            };
+            for affix in affixes {
+                let affix = String::try_from(affix)?;
+                let found = if name == "startswith" {
+                    s.starts_with(&affix)
+                } else {
+                    s.ends_with(&affix)
+                };
+                if found {
+                    return Ok(Value::Boolean(true));
+                }
+            }
+            Ok(Value::Boolean(false))
+        }
+        "split" => {
+            let separator: Option<String> = match args.get_optional(0, "sep")? {
+                None | Some(Value::None) => None,
+                Some(separator) => Some(String::try_from(separator)?),
+            };
+            let max_split: i64 = args.get_optional(1, "maxsplit")?.unwrap_or(-1);
+            let parts: Vec<&str> = match (separator, max_split) {
+                (Some(separator), _) if separator.is_empty() => {
+                    return Err(Error::from(RenderErrorKind::InvalidOperation));
+                }
+                (Some(separator), max_split) if max_split >= 0 => s
+                    .splitn(max_split as usize + 1, separator.as_str())
+                    .collect(),
+                (Some(separator), _) => s.split(separator.as_str()).collect(),
+                (None, max_split) if max_split >= 0 => {
+                    split_whitespace_n(s, max_split as usize + 1)
+                }
+                (None, _) => s.split_whitespace().collect(),
+            };
+            Ok(Value::ValuesList(
+                parts
+                    .into_iter()
+                    .map(|part| Value::String(part.to_string()))
+                    .collect(),
+            ))
+        }
+        "replace" => {
+            let old: String = args.get(0, "old")?;
+            let new: String = args.get(1, "new")?;
+            let count = args
+                .get_optional::<i64>(2, "count")?
+                .and_then(|count| usize::try_from(count).ok());
+            let matches = if old.is_empty() {
+                s.chars().count() + 1
+            } else {
+                s.matches(old.as_str()).count()
+            };
+            let replaced = count.map_or(matches, |count| count.min(matches));
+            check_length(s.len().saturating_add(replaced.saturating_mul(new.len())))?;
+            match count {
+                Some(count) => Ok(Value::String(s.replacen(&old, &new, count))),
+                None => Ok(Value::String(s.replace(&old, &new))),
+            }
+        }
+        "format" => format(s, &args),
+        "join" => {
+            let items: ValuesList = args.get(0, "iterable")?;
+            let items: Vec<String> = items.iter().map(Value::to_string).collect();
+            Ok(Value::String(items.join(s)))
+        }
+        _ => Err(Error::from(RenderErrorKind::InvalidOperation)),
+    }
+}
+
+/// Splits on runs of whitespace into at most `parts` parts, keeping the rest of the string
+/// in the last one as Python does.
+fn split_whitespace_n(s: &str, parts: usize) -> Vec<&str> {
+    let mut result = vec![];
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        if result.len() + 1 == parts {
+            result.push(rest);
+            break;
+        }
+        match rest.find(char::is_whitespace) {
+            Some(end) => {
+                result.push(&rest[..end]);
+                rest = rest[end..].trim_start();
+            }
+            None => {
+                result.push(rest);
+                break;
+            }
+        }
+    }
+    result
+}
+
+/// `str.format`, with automatic (`{}`), positional (`{0}`) and keyword (`{name}`) fields, each
+/// optionally followed by a `:spec` as described in `format_field`.
+fn format(s: &str, args: &CallArgs) -> Result<Value> {
+    let mut result = String::new();
+    let mut next_index = 0;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(Error::from(RenderErrorKind::InvalidOperation)),
+                    }
+                }
+                let spec = match field.find(':') {
+                    Some(colon) => {
+                        let spec = field[colon + 1..].to_string();
+                        field.truncate(colon);
+                        spec
+                    }
+                    None => String::new(),
+                };
+                if field.is_empty() {
+                    field = next_index.to_string();
+                    next_index += 1;
+                }
+                let value = if let Ok(index) = field.parse::<usize>() {
+                    args.positional.get(index)
+                } else {
+                    args.keyword.get(&field)
+                };
+                match value {
+                    Some(value) => result.push_str(&format_field(value, &spec)?),
+                    None => return Err(Error::from(RenderErrorKind::MissingArgument(field))),
+                }
+            }
+            '}' => return Err(Error::from(RenderErrorKind::InvalidOperation)),
+            c => result.push(c),
+        }
+    }
+    Ok(Value::String(result))
+}
+
+/// Formats `value` following the `[[fill]align][sign][0][width][.precision][type]` spec of
+/// Python, with the `s`, `d`, `b`, `o`, `x`, `X`, `f` and `F` types. Other specs, and types
+/// which don't suit the value, are an invalid operation.
+fn format_field(value: &Value, spec: &str) -> Result<String> {
+    let invalid = || Error::from(RenderErrorKind::InvalidOperation);
+    let spec: Vec<char> = spec.chars().collect();
+    let mut idx = 0;
+    let is_align = |c: Option<&char>| matches!(c, Some('<' | '>' | '^' | '='));
+    let (mut fill, mut align) = (None, None);
+    if is_align(spec.get(1)) {
+        fill = Some(spec[0]);
+        align = Some(spec[1]);
+        idx = 2;
+    } else if is_align(spec.first()) {
+        align = Some(spec[0]);
+        idx = 1;
+    }
+    let sign = match spec.get(idx) {
+        Some(c @ ('+' | '-' | ' ')) => {
+            idx += 1;
+            Some(*c)
+        }
+        _ => None,
+    };
+    if spec.get(idx) == Some(&'0') {
+        idx += 1;
+        fill = fill.or(Some('0'));
+        align = align.or(Some('='));
+    }
+    // Widths and precisions beyond the maximum length of values are rejected
+    let number = |idx: &mut usize| -> Result<Option<usize>> {
+        let digits: String = spec[*idx..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        *idx += digits.len();
+        let number = digits.parse().map_err(|_| invalid())?;
+        check_length(number)?;
+        Ok(Some(number))
+    };
+    let width = number(&mut idx)?.unwrap_or(0);
+    let precision = if spec.get(idx) == Some(&'.') {
+        idx += 1;
+        Some(number(&mut idx)?.ok_or_else(invalid)?)
+    } else {
+        None
+    };
+    let kind = spec.get(idx).copied();
+    if idx + usize::from(kind.is_some()) != spec.len() {
+        return Err(invalid());
+    }
+
+    let fixed = |number: f64| {
+        let text = format!("{:.*}", precision.unwrap_or(6), number.abs());
+        (number < 0.0, text)
+    };
+    let (negative, digits) = match (kind, value) {
+        (None | Some('s'), Value::String(s) | Value::SafeString(s)) if sign.is_none() => {
+            let text: String = match precision {
+                Some(precision) => s.chars().take(precision).collect(),
+                None => s.clone(),
+            };
+            (false, text)
+        }
+        (None, Value::Integer(_) | Value::Double(_)) if precision.is_none() => {
+            let text = value.to_string();
+            match text.strip_prefix('-') {
+                Some(text) => (true, text.to_string()),
+                None => (false, text),
+            }
+        }
+        (Some(kind @ ('d' | 'b' | 'o' | 'x' | 'X')), Value::Integer(number))
+            if precision.is_none() =>
+        {
+            let magnitude = number.unsigned_abs();
+            let text = match kind {
+                'b' => format!("{:b}", magnitude),
+                'o' => format!("{:o}", magnitude),
+                'x' => format!("{:x}", magnitude),
+                'X' => format!("{:X}", magnitude),
+                _ => magnitude.to_string(),
+            };
+            (*number < 0, text)
+        }
+        (Some('f' | 'F'), Value::Integer(number)) => fixed(*number as f64),
+        (Some('f' | 'F'), Value::Double(number)) => fixed(*number),
+        _ => return Err(invalid()),
+    };
+    let is_number = !matches!(value, Value::String(_) | Value::SafeString(_));
+    let sign = match (negative, sign) {
+        (true, _) => "-",
+        (false, Some('+')) => "+",
+        (false, Some(' ')) => " ",
+        _ => "",
+    };
+    let fill = fill.unwrap_or(' ');
+    let padding = width.saturating_sub(sign.chars().count() + digits.chars().count());
+    let pad = |count: usize| fill.to_string().repeat(count);
+    let default_align = if is_number { '>' } else { '<' };
+    Ok(match align.unwrap_or(default_align) {
+        '<' => format!("{}{}{}", sign, digits, pad(padding)),
+        '^' => format!(
+            "{}{}{}{}",
+            pad(padding / 2),
+            sign,
+            digits,
+            pad(padding - padding / 2)
+        ),
+        '=' if is_number => format!("{}{}{}", sign, pad(padding), digits),
+        '=' => return Err(invalid()),
+        _ => format!("{}{}{}", pad(padding), sign, digits),
+    })
+}
@@ -1,3 +1,4 @@
+use crate::error::{Error, RenderErrorKind, Result};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -5,12 +6,19 @@ pub enum Value {
     #[default]
     Empty,
     Error,
+    None,
     Boolean(bool),
     String(String),
+    /// Markup which is written as is when autoescaping.
+    SafeString(String),
     Integer(i64),
     Double(f64),
     ValuesList(ValuesList),
     ValuesMap(ValuesMap),
+    #[serde(skip)]
+    Callable(Callable),
+    #[serde(skip)]
+    Namespace(Namespace),
 }
 
 impl fmt::Display for Value {
@@ -18,10 +26,12 @@ impl fmt::Display for Value {
         match self {
             Value::Empty => write!(f, ""),
             Value::Error => write!(f, ""),
+            Value::None => write!(f, "None"),
             Value::Boolean(boolean) => write!(f, "{}", boolean),
             Value::Integer(integer) => write!(f, "{}", integer),
             Value::Double(float) => write!(f, "{:?}", float),
             Value::String(string) => write!(f, "{}", string),
+            Value::SafeString(string) => write!(f, "{}", string),
             Value::ValuesList(tuple) => {
                 write!(f, "[")?;
                 for (idx, value) in tuple.iter().enumerate() {
@@ -50,15 +60,80 @@ impl fmt::Display for Value {
                 }
                 write!(f, "}}")
             }
+            Value::Callable(callable) => write!(f, "{}", callable),
+            Value::Namespace(namespace) => write!(f, "{}", namespace),
         }
     }
 }
+
+/// How deeply lists, dicts and namespaces assigned to variables may nest, so that values
+/// built up by loops can still be copied, written and dropped without overflowing the stack.
+const MAX_VALUE_DEPTH: usize = 128;
+
+/// How many characters a string, or items a list, built by the template may hold, so that
+/// templates such as `{{ 'a' * 100000000000000 }}` fail instead of exhausting the memory.
+pub(crate) const MAX_LENGTH: usize = 1 << 20;
+
+/// Fails if a string or list of `len` bytes or items would exceed `MAX_LENGTH`.
+pub(crate) fn check_length(len: usize) -> Result<()> {
+    if len > MAX_LENGTH {
+        Err(Error::from(RenderErrorKind::ValueTooLong))
+    } else {
+        Ok(())
+    }
+}
+
+impl Value {
+    /// Fails if the value nests lists, dicts and namespaces deeper than `MAX_VALUE_DEPTH`,
+    /// as a namespace holding itself does.
+    pub(crate) fn check_depth(&self) -> Result<()> {
+        if self.nests_deeper_than(MAX_VALUE_DEPTH) {
+            Err(Error::from(RenderErrorKind::ValueTooDeep))
+        } else {
+            Ok(())
+        }
+    }
+    fn nests_deeper_than(&self, levels: usize) -> bool {
+        match self {
+            Value::ValuesList(values) => {
+                levels == 0
+                    || values
+                        .iter()
+                        .any(|value| value.nests_deeper_than(levels - 1))
+            }
+            Value::ValuesMap(values) => {
+                levels == 0
+                    || values
+                        .values()
+                        .any(|value| value.nests_deeper_than(levels - 1))
+            }
+            Value::Namespace(namespace) => {
+                levels == 0
+                    || namespace
+                        .0
+                        .read()
+                        .unwrap()
+                        .values()
+                        .any(|value| value.nests_deeper_than(levels - 1))
+            }
+            _ => false,
+        }
+    }
+}
+
 pub type ValuesMap = std::collections::BTreeMap<String, Value>;
 
 pub type ValuesList = Vec<Value>;
 
+mod callable;
 mod filters;
 mod from;
+mod methods;
+mod namespace;
 mod ops;
+mod testers;
+
+pub use callable::{CallArgs, Callable};
+pub use namespace::Namespace;
 
 pub mod visitors;
@@ -0,0 +1,43 @@
+use super::{Value, ValuesMap};
+use crate::error::Result;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Attributes shared by every copy of the value, so they can be changed from any scope.
+#[derive(Clone, Debug, Default)]
+pub struct Namespace(pub(crate) Arc<RwLock<ValuesMap>>);
+
+impl Namespace {
+    pub fn new(attributes: ValuesMap) -> Self {
+        Self(Arc::new(RwLock::new(attributes)))
+    }
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.0.read().unwrap().get(name).cloned()
+    }
+    pub fn set(&self, name: String, value: Value) {
+        self.0.write().unwrap().insert(name, value);
+    }
+    /// Sets an attribute from a template, unless that nests the namespace too deeply, as
+    /// holding itself would.
+    pub(crate) fn assign(&self, name: String, value: Value) -> Result<()> {
+        let previous = self.0.write().unwrap().insert(name.clone(), value);
+        let checked = Value::Namespace(self.clone()).check_depth();
+        if checked.is_err() {
+            let mut attributes = self.0.write().unwrap();
+            match previous {
+                Some(previous) => attributes.insert(name, previous),
+                None => attributes.remove(&name),
+            };
+        }
+        checked
+    }
+    pub fn attributes(&self) -> ValuesMap {
+        self.0.read().unwrap().clone()
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<Namespace {}>", Value::ValuesMap(self.attributes()))
+    }
+}
@@ -1,21 +1,59 @@
-use super::Value;
+use super::{check_length, Value};
+use crate::error::Result;
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
+use std::convert::TryFrom;
 use std::ops::{Add, BitAnd, BitOr, Div, Mul, Neg, Not, Rem, Sub};
 
+/// Integer arithmetic that overflows, or divides by zero, results in an error value.
+fn checked_integer(result: Option<i64>) -> Value {
+    result.map_or(Value::Error, Value::Integer)
+}
+
+/// Repeats the string `times` times. A negative count gives an empty string.
+fn repeat(s: &str, times: i64) -> Result<Value> {
+    let times = usize::try_from(times).unwrap_or(0);
+    check_length(s.len().saturating_mul(times))?;
+    Ok(Value::String(s.repeat(times)))
+}
+
+/// Repeats the items of the list `times` times. A negative count gives an empty list.
+fn repeat_list(list: &[Value], times: i64) -> Result<Value> {
+    let times = usize::try_from(times).unwrap_or(0);
+    check_length(list.len().saturating_mul(times))?;
+    let len = list.len() * times;
+    Ok(Value::ValuesList(
+        list.iter().cycle().take(len).cloned().collect(),
+    ))
+}
+
+impl Value {
+    /// `self * other`, which fails when it repeats a string or list beyond the maximum length.
+    pub fn multiply(self, other: Self) -> Result<Self> {
+        match (self, other) {
+            (Value::String(left), Value::Integer(right)) => repeat(&left, right),
+            (Value::String(left), Value::Double(right)) => repeat(&left, right as i64),
+            (Value::ValuesList(left), Value::Integer(right)) => repeat_list(&left, right),
+            (left, right) => Ok(left * right),
+        }
+    }
+}
+
 impl Add for Value {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
         match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left + right),
+            (Value::Integer(left), Value::Integer(right)) => {
+                checked_integer(left.checked_add(right))
+            }
             (Value::Double(left), Value::Integer(right)) => Value::Double(left + right as f64),
             (Value::Integer(left), Value::Double(right)) => Value::Double(left as f64 + right),
             (Value::Double(left), Value::Double(right)) => Value::Double(left + right),
-            (Value::Integer(left), Value::Boolean(true)) => Value::Integer(left + 1),
+            (Value::Integer(left), Value::Boolean(true)) => checked_integer(left.checked_add(1)),
             (Value::Integer(left), Value::Boolean(false)) => Value::Integer(left),
             (Value::Double(left), Value::Boolean(true)) => Value::Double(left + 1_f64),
             (Value::Double(left), Value::Boolean(false)) => Value::Double(left),
-            (Value::Boolean(true), Value::Integer(right)) => Value::Integer(right + 1),
+            (Value::Boolean(true), Value::Integer(right)) => checked_integer(right.checked_add(1)),
             (Value::Boolean(false), Value::Integer(right)) => Value::Integer(right),
             (Value::Boolean(true), Value::Double(right)) => Value::Double(right + 1_f64),
             (Value::Boolean(false), Value::Double(right)) => Value::Double(right),
@@ -32,7 +70,9 @@ impl Mul for Value {
 
     fn mul(self, other: Self) -> Self {
         match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left * right),
+            (Value::Integer(left), Value::Integer(right)) => {
+                checked_integer(left.checked_mul(right))
+            }
             (Value::Double(left), Value::Integer(right)) => Value::Double(left * right as f64),
             (Value::Integer(left), Value::Double(right)) => Value::Double(left as f64 * right),
             (Value::Double(left), Value::Double(right)) => Value::Double(left * right),
@@ -45,10 +85,13 @@ impl Mul for Value {
             (Value::Boolean(true), Value::Double(right)) => Value::Double(right),
             (Value::Boolean(false), Value::Double(_)) => Value::Double(0.0),
             (Value::String(left), Value::Integer(right)) => {
-                Value::String(left.repeat(right as usize))
+                repeat(&left, right).unwrap_or(Value::Error)
             }
             (Value::String(left), Value::Double(right)) => {
-                Value::String(left.repeat(right as usize))
+                repeat(&left, right as i64).unwrap_or(Value::Error)
+            }
+            (Value::ValuesList(left), Value::Integer(right)) => {
+                repeat_list(&left, right).unwrap_or(Value::Error)
             }
             (Value::String(left), Value::Boolean(true)) => Value::String(left),
             (Value::String(_), Value::Boolean(false)) => Value::String("".to_string()),
@@ -78,7 +121,9 @@ impl Sub for Value {
 
     fn sub(self, other: Self) -> Self {
         match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left - right),
+            (Value::Integer(left), Value::Integer(right)) => {
+                checked_integer(left.checked_sub(right))
+            }
             (Value::Double(left), Value::Integer(right)) => Value::Double(left - right as f64),
             (Value::Integer(left), Value::Double(right)) => Value::Double(left as f64 - right),
             (Value::Double(left), Value::Double(right)) => Value::Double(left - right),
@@ -92,7 +137,9 @@ impl Rem for Value {
 
     fn rem(self, other: Self) -> Self {
         match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left % right),
+            (Value::Integer(left), Value::Integer(right)) => {
+                checked_integer(left.checked_rem(right))
+            }
             (Value::Double(left), Value::Integer(right)) => Value::Double(left % right as f64),
             (Value::Integer(left), Value::Double(right)) => Value::Double(left as f64 % right),
             (Value::Double(left), Value::Double(right)) => Value::Double(left % right),
@@ -106,7 +153,7 @@ impl Neg for Value {
 
     fn neg(self) -> Self {
         match self {
-            Value::Integer(value) => Value::Integer(-value),
+            Value::Integer(value) => checked_integer(value.checked_neg()),
             Value::Double(value) => Value::Double(-value),
             _ => Value::Error,
         }
@@ -126,7 +173,14 @@ impl Not for Value {
 impl Value {
     pub fn pow(&self, other: Self) -> Self {
         match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left.pow(right as u32)),
+            (Value::Integer(left), Value::Integer(right)) if right >= 0 => checked_integer(
+                u32::try_from(right)
+                    .ok()
+                    .and_then(|right| left.checked_pow(right)),
+            ),
+            (Value::Integer(left), Value::Integer(right)) => {
+                Value::Double((*left as f64).powf(right as f64))
+            }
             (Value::Double(left), Value::Integer(right)) => Value::Double(left.powf(right as f64)),
             (Value::Integer(left), Value::Double(right)) => {
                 let left = *left as f64;
@@ -151,32 +205,54 @@ impl Ord for Value {
         match (self, other) {
             (Value::Integer(left), Value::Integer(right)) => left.cmp(right),
             (Value::Integer(left), Value::Double(right)) => {
-                (*left as f64).partial_cmp(right).unwrap()
+                (*left as f64).partial_cmp(right).unwrap_or(Ordering::Equal)
             }
-            (Value::Double(left), Value::Integer(right)) => {
-                left.partial_cmp(&(*right as f64)).unwrap()
+            (Value::Double(left), Value::Integer(right)) => left
+                .partial_cmp(&(*right as f64))
+                .unwrap_or(Ordering::Equal),
+            (Value::Double(left), Value::Double(right)) => {
+                left.partial_cmp(right).unwrap_or(Ordering::Equal)
             }
-            (Value::Double(left), Value::Double(right)) => left.partial_cmp(right).unwrap(),
             (Value::Integer(left), Value::Boolean(true)) => left.cmp(&(1_i64)),
             (Value::Integer(left), Value::Boolean(false)) => left.cmp(&(1_i64)),
-            (Value::Double(left), Value::Boolean(true)) => left.partial_cmp(&(1_f64)).unwrap(),
-            (Value::Double(left), Value::Boolean(false)) => left.partial_cmp(&(0_f64)).unwrap(),
+            (Value::Double(left), Value::Boolean(true)) => {
+                left.partial_cmp(&(1_f64)).unwrap_or(Ordering::Equal)
+            }
+            (Value::Double(left), Value::Boolean(false)) => {
+                left.partial_cmp(&(0_f64)).unwrap_or(Ordering::Equal)
+            }
             (Value::Boolean(left), Value::Boolean(right)) => left.cmp(right),
             (Value::Boolean(true), Value::Integer(right)) => 1_i64.cmp(right),
             (Value::Boolean(false), Value::Integer(right)) => 0i64.cmp(right),
-            (Value::Boolean(true), Value::Double(right)) => 1_f64.partial_cmp(right).unwrap(),
-            (Value::Boolean(false), Value::Double(right)) => 0_f64.partial_cmp(right).unwrap(),
+            (Value::Boolean(true), Value::Double(right)) => {
+                1_f64.partial_cmp(right).unwrap_or(Ordering::Equal)
+            }
+            (Value::Boolean(false), Value::Double(right)) => {
+                0_f64.partial_cmp(right).unwrap_or(Ordering::Equal)
+            }
             (Value::ValuesList(left), Value::ValuesList(right)) => left.cmp(right),
             (Value::ValuesMap(left), Value::ValuesMap(right)) => left.cmp(right),
-            (Value::String(left), Value::String(right)) => left.cmp(right),
-            (Value::String(_), _) => Ordering::Greater,
-            (_, Value::String(_)) => Ordering::Less,
+            (
+                Value::String(left) | Value::SafeString(left),
+                Value::String(right) | Value::SafeString(right),
+            ) => left.cmp(right),
+            (Value::String(_) | Value::SafeString(_), _) => Ordering::Greater,
+            (_, Value::String(_) | Value::SafeString(_)) => Ordering::Less,
             (Value::ValuesList(_), _) => Ordering::Greater,
             (_, Value::ValuesList(_)) => Ordering::Less,
+            (Value::None, Value::None) => Ordering::Equal,
+            (_, Value::None) => Ordering::Greater,
+            (Value::None, _) => Ordering::Less,
             (_, Value::Empty) => Ordering::Greater,
             (_, Value::Error) => Ordering::Greater,
             (Value::Empty, _) => Ordering::Less,
             (Value::Error, _) => Ordering::Less,
+            (Value::Callable(_), Value::Callable(_)) => Ordering::Equal,
+            (Value::Callable(_), _) => Ordering::Less,
+            (_, Value::Callable(_)) => Ordering::Greater,
+            (Value::Namespace(_), Value::Namespace(_)) => Ordering::Equal,
+            (Value::Namespace(_), _) => Ordering::Less,
+            (_, Value::Namespace(_)) => Ordering::Greater,
             (Value::ValuesMap(_), _) => Ordering::Greater,
             (_, Value::ValuesMap(_)) => Ordering::Less,
         }
@@ -191,7 +267,13 @@ impl PartialEq for Value {
             (Value::Double(left), Value::Integer(right)) => *left == (*right as f64),
             (Value::Double(left), Value::Double(right)) => left == right,
             (Value::Boolean(left), Value::Boolean(right)) => left == right,
-            (Value::String(left), Value::String(right)) => left == right,
+            (Value::None, Value::None) => true,
+            (
+                Value::String(left) | Value::SafeString(left),
+                Value::String(right) | Value::SafeString(right),
+            ) => left == right,
+            (Value::ValuesList(left), Value::ValuesList(right)) => left == right,
+            (Value::ValuesMap(left), Value::ValuesMap(right)) => left == right,
             _ => false,
         }
     }
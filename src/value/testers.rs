@@ -0,0 +1,67 @@
+use super::Value;
+use crate::error::{Error, RenderErrorKind, Result};
+use std::sync::Arc;
+
+impl Value {
+    pub fn is_divisible_by(&self, num: &Value) -> Result<bool> {
+        match (self, num) {
+            (Value::Integer(value), Value::Integer(num)) if *num != 0 => Ok(value % num == 0),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+    /// Tells whether `item` is an element of a list, a key of a map or a substring of a string.
+    pub fn contains(&self, item: &Value) -> Result<bool> {
+        match (self, item) {
+            (Value::ValuesList(list), item) => Ok(list.contains(item)),
+            (Value::ValuesMap(map), Value::String(key) | Value::SafeString(key)) => {
+                Ok(map.contains_key(key))
+            }
+            (Value::ValuesMap(_), _) => Ok(false),
+            (
+                Value::String(string) | Value::SafeString(string),
+                Value::String(substring) | Value::SafeString(substring),
+            ) => Ok(string.contains(substring.as_str())),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+    }
+    /// Tells whether the items of the value can be iterated over, as strings, lists and maps.
+    pub fn is_iterable(&self) -> bool {
+        matches!(
+            self,
+            Value::String(_) | Value::SafeString(_) | Value::ValuesList(_) | Value::ValuesMap(_)
+        )
+    }
+    pub fn is_lower(&self) -> bool {
+        match self {
+            Value::String(s) | Value::SafeString(s) => {
+                s.chars().any(char::is_lowercase) && !s.chars().any(char::is_uppercase)
+            }
+            _ => false,
+        }
+    }
+    pub fn is_upper(&self) -> bool {
+        match self {
+            Value::String(s) | Value::SafeString(s) => {
+                s.chars().any(char::is_uppercase) && !s.chars().any(char::is_lowercase)
+            }
+            _ => false,
+        }
+    }
+    /// Tells whether the value is a number whose remainder of the division by two is `remainder`,
+    /// so that neither `odd` nor `even` holds for `1.5` or for values which are not numbers.
+    pub fn has_parity(&self, remainder: i64) -> bool {
+        match self {
+            Value::Integer(number) => number.rem_euclid(2) == remainder,
+            Value::Double(number) => number.rem_euclid(2.0) == remainder as f64,
+            _ => false,
+        }
+    }
+    /// Values are copied around while rendering, so two values are the same object when they
+    /// are of the same type and equal.
+    pub fn is_same_as(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Namespace(left), Value::Namespace(right)) => Arc::ptr_eq(&left.0, &right.0),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other) && self == other,
+        }
+    }
+}
@@ -1,13 +1,24 @@
 use super::Value;
+use crate::error::{Error, ParseErrorKind, RenderErrorKind, Result};
 use crate::expression_evaluator::BinaryOperation;
+use std::convert::TryFrom;
 pub struct BinaryMathOperation;
 
+/// Turns the error value of a failed operation, such as an overflow or operands of the wrong
+/// type, into an error.
+pub fn operation_result(value: Value) -> Result<Value> {
+    match value {
+        Value::Error => Err(Error::from(RenderErrorKind::InvalidOperation)),
+        value => Ok(value),
+    }
+}
+
 impl BinaryMathOperation {
-    pub fn apply(op: &BinaryOperation, left: Value, right: Value) -> Value {
-        match op {
+    pub fn apply(op: &BinaryOperation, left: Value, right: Value) -> Result<Value> {
+        let value = match op {
             BinaryOperation::Plus => left + right,
             BinaryOperation::Minus => left - right,
-            BinaryOperation::Mul => left * right,
+            BinaryOperation::Mul => left.multiply(right)?,
             BinaryOperation::Div => left / right,
             BinaryOperation::Modulo => left % right,
             BinaryOperation::DivInteger => {
@@ -15,7 +26,7 @@ impl BinaryMathOperation {
                 if let Value::Double(num) = value {
                     Value::Integer(num as i64)
                 } else {
-                    todo!()
+                    return Err(Error::from(RenderErrorKind::InvalidOperation));
                 }
             }
             BinaryOperation::Pow => left.pow(right),
@@ -27,22 +38,142 @@ impl BinaryMathOperation {
             BinaryOperation::LogicalNe => Value::Boolean(left != right),
             BinaryOperation::LogicalOr => left | right,
             BinaryOperation::LogicalAnd => left & right,
-            BinaryOperation::StringConcat => Value::String(left.to_string() + &right.to_string()),
+            BinaryOperation::In | BinaryOperation::NotIn => match right.contains(&left) {
+                Ok(contains) => Value::Boolean(contains == matches!(op, BinaryOperation::In)),
+                Err(_) => return Err(Error::from(RenderErrorKind::InvalidOperation)),
+            },
+            BinaryOperation::StringConcat => BinaryMathOperation::concat(left, right, false)?,
+        };
+        operation_result(value)
+    }
+    /// `left ~ right`. When autoescaping and either side is marked safe, the other side is
+    /// escaped and the result is marked safe, as Jinja2's `markup_join` does.
+    pub fn concat(left: Value, right: Value, autoescape: bool) -> Result<Value> {
+        let is_markup =
+            matches!(left, Value::SafeString(_)) || matches!(right, Value::SafeString(_));
+        if autoescape && is_markup {
+            let text = left.escape()?.to_string() + &right.escape()?.to_string();
+            Ok(Value::SafeString(text))
+        } else {
+            Ok(Value::String(left.to_string() + &right.to_string()))
         }
     }
 }
 
 pub struct Subscription;
 impl Subscription {
-    pub fn apply(value: Value, subscript: Value) -> Value {
-        // TODO: Change to Result<Value>
-        match (value, subscript) {
+    pub fn apply(value: Value, subscript: Value) -> Result<Value> {
+        let item = match (value, subscript) {
             (Value::String(st), Value::Integer(idx)) => {
-                Value::String(st.chars().nth(idx as usize).unwrap().to_string())
+                let len = st.chars().count();
+                Subscription::index(idx, len)
+                    .and_then(|idx| st.chars().nth(idx))
+                    .map(|ch| Value::String(ch.to_string()))
+            }
+            (Value::SafeString(st), Value::Integer(idx)) => {
+                let len = st.chars().count();
+                Subscription::index(idx, len)
+                    .and_then(|idx| st.chars().nth(idx))
+                    .map(|ch| Value::SafeString(ch.to_string()))
+            }
+            (Value::ValuesList(tuple), Value::Integer(idx)) => {
+                Subscription::index(idx, tuple.len()).map(|idx| tuple[idx].clone())
+            }
+            (Value::ValuesMap(dict), Value::String(key)) => match dict.get(&key) {
+                Some(item) => Some(item.clone()),
+                None => return Err(Error::from(ParseErrorKind::UndefinedValue(key))),
+            },
+            (Value::Namespace(namespace), Value::String(key)) => match namespace.get(&key) {
+                Some(item) => Some(item),
+                None => return Err(Error::from(ParseErrorKind::UndefinedValue(key))),
+            },
+            (Value::Callable(callable), Value::String(key)) => match callable.attribute(&key) {
+                Some(item) => Some(item.clone()),
+                None => return Err(Error::from(ParseErrorKind::UndefinedValue(key))),
+            },
+            _ => return Err(Error::from(RenderErrorKind::InvalidValueType)),
+        };
+        item.ok_or_else(|| Error::from(RenderErrorKind::IndexOutOfRange))
+    }
+    /// `value[subscript] = item`, for the containers items can be stored in.
+    pub fn assign(value: &mut Value, subscript: Value, item: Value) -> Result<()> {
+        match (value, subscript) {
+            (Value::ValuesList(list), Value::Integer(idx)) => {
+                match Subscription::index(idx, list.len()) {
+                    Some(idx) => list[idx] = item,
+                    None => return Err(Error::from(RenderErrorKind::IndexOutOfRange)),
+                }
+            }
+            (Value::ValuesMap(dict), Value::String(key)) => {
+                dict.insert(key, item);
+            }
+            (Value::Namespace(namespace), Value::String(key)) => namespace.assign(key, item)?,
+            _ => return Err(Error::from(RenderErrorKind::InvalidValueType)),
+        }
+        Ok(())
+    }
+    /// Position of the item at `idx` in a sequence of `len` items, negative indices counting
+    /// from the end.
+    fn index(idx: i64, len: usize) -> Option<usize> {
+        let idx = if idx < 0 {
+            idx.checked_add(i64::try_from(len).ok()?)?
+        } else {
+            idx
+        };
+        usize::try_from(idx).ok().filter(|idx| *idx < len)
+    }
+    /// `value[start:stop:step]`, following the rules of Python slices.
+    pub fn slice(
+        value: Value,
+        start: Option<i64>,
+        stop: Option<i64>,
+        step: Option<i64>,
+    ) -> Result<Value> {
+        let step = step.unwrap_or(1);
+        if step == 0 {
+            return Err(Error::from(RenderErrorKind::InvalidOperation));
+        }
+        let positions = |len: usize| {
+            let len = i64::try_from(len).unwrap_or(i64::MAX);
+            let (lower, upper) = if step < 0 { (-1, len - 1) } else { (0, len) };
+            let clamp = |bound: i64| {
+                if bound < 0 {
+                    bound.saturating_add(len).max(lower)
+                } else {
+                    bound.min(upper)
+                }
+            };
+            let start = start.map_or(if step < 0 { upper } else { lower }, clamp);
+            let stop = stop.map_or(if step < 0 { lower } else { upper }, clamp);
+            let mut positions = vec![];
+            let mut position = start;
+            while (step > 0 && position < stop) || (step < 0 && position > stop) {
+                positions.push(position as usize);
+                position = match position.checked_add(step) {
+                    Some(position) => position,
+                    None => break,
+                };
             }
-            (Value::ValuesList(tuple), Value::Integer(idx)) => tuple[idx as usize].clone(),
-            (Value::ValuesMap(dict), Value::String(key)) => dict.get(&key).unwrap().clone(),
-            _ => todo!(),
+            positions
+        };
+        let slice_chars = |st: String| -> String {
+            let chars: Vec<char> = st.chars().collect();
+            positions(chars.len())
+                .into_iter()
+                .map(|idx| chars[idx])
+                .collect()
+        };
+        match value {
+            Value::String(st) => Ok(Value::String(slice_chars(st))),
+            // Slices of safe markup stay safe, as Jinja2's `Markup` does
+            Value::SafeString(st) => Ok(Value::SafeString(slice_chars(st))),
+            Value::ValuesList(tuple) => Ok(Value::ValuesList(
+                positions(tuple.len())
+                    .into_iter()
+                    .map(|idx| tuple[idx].clone())
+                    .collect(),
+            )),
+            _ => Err(Error::from(RenderErrorKind::InvalidValueType)),
         }
     }
 }
@@ -0,0 +1,175 @@
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+use temple::{Template, TemplateEnv};
+
+fn assert_render_template_with_autoescape_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.settings_mut().autoescape = true;
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let default_context = ValuesMap::default();
+    let context = params.unwrap_or(default_context);
+    let result = template.render_as_string(context)?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+fn html_context() -> ValuesMap {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "html".to_string(),
+        Value::String("<script>alert('x')</script>".to_string()),
+    );
+    context
+}
+
+#[test]
+fn autoescape_expressions() -> Result<()> {
+    assert_render_template_with_autoescape_eq(
+        "<p>{{ html }}</p>",
+        "<p>&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;</p>",
+        Some(html_context()),
+    )
+}
+
+#[test]
+fn autoescape_safe_value() -> Result<()> {
+    assert_render_template_with_autoescape_eq(
+        "{{ html | safe }}",
+        "<script>alert('x')</script>",
+        Some(html_context()),
+    )
+}
+
+#[test]
+fn autoescape_no_double_escape() -> Result<()> {
+    assert_render_template_with_autoescape_eq("{{ \"&\" | escape }}", "&amp;", None)
+}
+
+#[test]
+fn autoescape_filtered_safe_value() -> Result<()> {
+    assert_render_template_with_autoescape_eq("{{ \"<b>\" | safe | upper }}", "<B>", None)
+}
+
+#[test]
+fn autoescape_disabled_by_default() -> Result<()> {
+    let temp_env = TemplateEnv::default();
+    let mut template = Template::new(&temp_env)?;
+    template.load("{{ html }}")?;
+    let result = template.render_as_string(html_context())?;
+    assert_eq!(result, "<script>alert('x')</script>".to_string());
+    Ok(())
+}
+
+#[test]
+fn autoescape_block() -> Result<()> {
+    let temp_env = TemplateEnv::default();
+    let mut template = Template::new(&temp_env)?;
+    template.load("{{ html }}|{% autoescape true %}{{ html }}{% endautoescape %}|{{ html }}")?;
+    let result = template.render_as_string(html_context())?;
+    assert_eq!(
+        result,
+        "<script>alert('x')</script>|&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;|<script>alert('x')</script>"
+            .to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn autoescape_block_disabled() -> Result<()> {
+    assert_render_template_with_autoescape_eq(
+        "{% autoescape false %}{{ html }}{% endautoescape %}",
+        "<script>alert('x')</script>",
+        Some(html_context()),
+    )
+}
+
+#[test]
+fn autoescape_macro_output_is_safe() -> Result<()> {
+    assert_render_template_with_autoescape_eq(
+        "{% macro bold(text) %}<b>{{ text }}</b>{% endmacro %}{{ bold(\"<i>\") }}",
+        "<b>&lt;i&gt;</b>",
+        None,
+    )
+}
+
+#[test]
+fn autoescape_set_block_is_safe() -> Result<()> {
+    assert_render_template_with_autoescape_eq(
+        "{% set link %}<a>{{ \"&\" }}</a>{% endset %}{{ link }}",
+        "<a>&amp;</a>",
+        None,
+    )
+}
+
+#[test]
+fn error_autoescape_not_boolean() -> Result<()> {
+    let result = assert_render_template_with_autoescape_eq(
+        "{% autoescape \"yes\" %}{% endautoescape %}",
+        "",
+        None,
+    );
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn autoescape_filters_keep_safe_mark() -> Result<()> {
+    assert_render_template_with_autoescape_eq(
+        "{{ html | safe | default('x') }}|{{ html | safe | string }}",
+        "<script>alert('x')</script>|<script>alert('x')</script>",
+        Some(html_context()),
+    )?;
+    assert_render_template_with_autoescape_eq(
+        "{% set x %}<i>{% endset %}{{ x | upper }}{{ x | lower }}{{ x | title }}{{ x | capitalize }}",
+        "<I><i><I><i>",
+        None,
+    )
+}
+
+#[test]
+fn autoescape_filters_drop_safe_mark() -> Result<()> {
+    assert_render_template_with_autoescape_eq("{{ '<b>' | safe | center(5) }}", " &lt;b&gt; ", None)
+}
+
+#[test]
+fn autoescape_custom_filter_gets_safe_value() -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.settings_mut().autoescape = true;
+    temp_env.add_filter("bold".to_string(), |value, _args, _context| match value {
+        Value::SafeString(s) => Ok(Value::SafeString(format!("<b>{}</b>", s))),
+        value => Ok(Value::String(format!("<b>{}</b>", value))),
+    });
+    let mut template = Template::new(&temp_env)?;
+    template.load("{{ '<i>' | safe | bold }}|{{ '<i>' | bold }}")?;
+    let result = template.render_as_string(ValuesMap::default())?;
+    assert_eq!(
+        result,
+        "<b><i></b>|&lt;b&gt;&lt;i&gt;&lt;/b&gt;".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn autoescape_concat_with_safe_value() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("s".to_string(), Value::String("<x>".to_string()));
+    assert_render_template_with_autoescape_eq(
+        "{{ s ~ s | safe }}|{{ s ~ s }}|{% autoescape false %}{{ s ~ s | safe }}{% endautoescape %}",
+        "&lt;x&gt;<x>|&lt;x&gt;&lt;x&gt;|<x><x>",
+        Some(context),
+    )
+}
+
+#[test]
+fn autoescape_subscripts_of_safe_value() -> Result<()> {
+    assert_render_template_with_autoescape_eq(
+        "{{ ('<b>' | safe)[:2] }}|{{ ('<b>' | safe)[0] }}|{{ '<b>'[:2] }}",
+        "<b|<|&lt;b",
+        None,
+    )
+}
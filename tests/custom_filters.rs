@@ -0,0 +1,130 @@
+use temple::error::{Error, RenderErrorKind, Result};
+use temple::value::{Value, ValuesMap};
+use temple::{Template, TemplateEnv};
+
+fn custom_filters_env<'a>() -> TemplateEnv<'a> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.add_filter("reverse".to_string(), |value, _args, _context| {
+        Ok(Value::String(value.to_string().chars().rev().collect()))
+    });
+    temp_env.add_filter("repeat".to_string(), |value, args, _context| {
+        let times = match args
+            .positional
+            .first()
+            .or_else(|| args.keyword.get("times"))
+        {
+            Some(Value::Integer(times)) => *times as usize,
+            Some(_) => return Err(Error::from(RenderErrorKind::InvalidValueType)),
+            None => 2,
+        };
+        let separator = args
+            .keyword
+            .get("sep")
+            .map(|sep| sep.to_string())
+            .unwrap_or_default();
+        Ok(Value::String(
+            vec![value.to_string(); times].join(&separator),
+        ))
+    });
+    temp_env.add_filter("prefixed".to_string(), |value, _args, context| {
+        Ok(Value::String(format!(
+            "{}{}",
+            context.find("prefix")?,
+            value
+        )))
+    });
+    temp_env
+}
+
+fn assert_render_template_with_custom_filters_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let temp_env = custom_filters_env();
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let result = template.render_as_string(params.unwrap_or_default())?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn custom_filter() -> Result<()> {
+    assert_render_template_with_custom_filters_eq("{{ 'abc' | reverse }}", "cba", None)?;
+    assert_render_template_with_custom_filters_eq("{{ 'abc' | reverse | upper }}", "CBA", None)
+}
+
+#[test]
+fn custom_filter_arguments() -> Result<()> {
+    assert_render_template_with_custom_filters_eq("{{ 'ab' | repeat }}", "abab", None)?;
+    assert_render_template_with_custom_filters_eq("{{ 'ab' | repeat(3) }}", "ababab", None)?;
+    assert_render_template_with_custom_filters_eq(
+        "{{ 'ab' | repeat(times=3, sep='-') }}",
+        "ab-ab-ab",
+        None,
+    )
+}
+
+#[test]
+fn custom_filter_context() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("prefix".to_string(), Value::String("> ".to_string()));
+    assert_render_template_with_custom_filters_eq(
+        "{{ 'text' | prefixed }}",
+        "> text",
+        Some(context),
+    )
+}
+
+#[test]
+fn custom_filter_in_statements() -> Result<()> {
+    assert_render_template_with_custom_filters_eq(
+        "{% filter reverse %}abc{% endfilter %}",
+        "cba",
+        None,
+    )?;
+    assert_render_template_with_custom_filters_eq(
+        "{% set x | repeat %}ab{% endset %}{{ x }}",
+        "abab",
+        None,
+    )
+}
+
+#[test]
+fn custom_filter_overrides_builtin() -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.add_filter("upper".to_string(), |_value, _args, _context| {
+        Ok(Value::String("overridden".to_string()))
+    });
+    let mut template = Template::new(&temp_env)?;
+    template.load("{{ 'abc' | upper }}")?;
+    let result = template.render_as_string(ValuesMap::default())?;
+    assert_eq!(result, "overridden".to_string());
+    Ok(())
+}
+
+#[test]
+fn custom_filter_error() -> Result<()> {
+    let result =
+        assert_render_template_with_custom_filters_eq("{{ 'ab' | repeat('x') }}", "", None);
+    assert!(result.is_err());
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid type of the value in the particular context".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_unknown_custom_filter_at_parse_time() -> Result<()> {
+    let temp_env = custom_filters_env();
+    let mut template = Template::new(&temp_env)?;
+    let result = template.load("{{ 'abc' | unknown }}");
+    assert!(result.is_err());
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: Unknown filter: unknown".to_string()
+    );
+    Ok(())
+}
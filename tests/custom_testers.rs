@@ -0,0 +1,89 @@
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+use temple::{Template, TemplateEnv};
+
+fn custom_testers_env<'a>() -> TemplateEnv<'a> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.add_test("shippable".to_string(), |value, _args, _context| {
+        Ok(match value {
+            Value::ValuesMap(order) => matches!(order.get("paid"), Some(Value::Boolean(true))),
+            _ => false,
+        })
+    });
+    temp_env.add_test("longer".to_string(), |value, args, _context| {
+        let length = match args.positional.first().or_else(|| args.keyword.get("than")) {
+            Some(Value::Integer(length)) => *length as usize,
+            _ => 0,
+        };
+        Ok(value.to_string().len() > length)
+    });
+    temp_env
+}
+
+fn assert_render_template_with_custom_testers_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let temp_env = custom_testers_env();
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let result = template.render_as_string(params.unwrap_or_default())?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+fn order_context(paid: bool) -> ValuesMap {
+    let mut order = ValuesMap::default();
+    order.insert("paid".to_string(), Value::Boolean(paid));
+    let mut context = ValuesMap::default();
+    context.insert("order".to_string(), Value::ValuesMap(order));
+    context
+}
+
+#[test]
+fn custom_test() -> Result<()> {
+    let template = "{% if order is shippable %}ship{% else %}wait{% endif %}";
+    assert_render_template_with_custom_testers_eq(template, "ship", Some(order_context(true)))?;
+    assert_render_template_with_custom_testers_eq(template, "wait", Some(order_context(false)))?;
+    assert_render_template_with_custom_testers_eq(
+        "{{ order is not shippable }}",
+        "true",
+        Some(order_context(false)),
+    )
+}
+
+#[test]
+fn custom_test_arguments() -> Result<()> {
+    assert_render_template_with_custom_testers_eq("{{ 'abc' is longer(2) }}", "true", None)?;
+    assert_render_template_with_custom_testers_eq("{{ 'abc' is longer 3 }}", "false", None)?;
+    assert_render_template_with_custom_testers_eq("{{ 'abc' is longer(than=1) }}", "true", None)
+}
+
+#[test]
+fn custom_test_overrides_builtin() -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.add_test("odd".to_string(), |_value, _args, _context| Ok(true));
+    let mut template = Template::new(&temp_env)?;
+    template.load("{{ 2 is odd }}")?;
+    let result = template.render_as_string(ValuesMap::default())?;
+    assert_eq!(result, "true".to_string());
+    Ok(())
+}
+
+#[test]
+fn error_unknown_test_suggestion() -> Result<()> {
+    let temp_env = custom_testers_env();
+    let mut template = Template::new(&temp_env)?;
+    let result = template.load("{{ order is shipable }}");
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: Unknown test: shipable. Did you mean 'shippable'?".to_string()
+    );
+    let result = template.load("{{ 1 is devisibleby(3) }}");
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: Unknown test: devisibleby. Did you mean 'divisibleby'?".to_string()
+    );
+    Ok(())
+}
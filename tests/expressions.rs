@@ -1,6 +1,21 @@
 use super::utils::assert_render_template_eq;
 use temple::error::Result;
 use temple::value::{Value, ValuesMap};
+use temple::{Template, TemplateEnv, UndefinedBehavior};
+
+fn assert_render_template_with_lenient_undefined_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.settings_mut().undefined = UndefinedBehavior::Lenient;
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let result = template.render_as_string(params.unwrap_or_default())?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
 
 #[test]
 fn basic_math_expression() -> Result<()> {
@@ -97,3 +112,136 @@ fn accessors() -> Result<()> {
     assert_render_template_eq("{{ (0, 1, 2)[2] }}", "2", None)?;
     assert_render_template_eq("{{ {\"one\": 1, \"two\":2}[\"two\"] }}", "2", None)
 }
+
+#[test]
+fn negative_indices() -> Result<()> {
+    assert_render_template_eq("{{ [0, 1, 2][-1] }}", "2", None)?;
+    assert_render_template_eq("{{ [0, 1, 2][-3] }}", "0", None)?;
+    assert_render_template_eq("{{ 'hello'[-2] }}", "l", None)
+}
+
+#[test]
+fn slices() -> Result<()> {
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][1:3] }}", "[1, 2]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][:2] }}", "[0, 1]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][3:] }}", "[3, 4]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][::2] }}", "[0, 2, 4]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][-2:] }}", "[3, 4]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][1:-1] }}", "[1, 2, 3]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][::-2] }}", "[4, 2, 0]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2, 3, 4][3:0:-1] }}", "[3, 2, 1]", None)?;
+    assert_render_template_eq("{{ [0, 1, 2][5:10] }}", "[]", None)?;
+    assert_render_template_eq("{{ 'hello'[::-1] }}", "olleh", None)?;
+    assert_render_template_eq("{{ 'hello'[1:4] | upper }}", "ELL", None)?;
+    assert_render_template_eq("{{ 'hello'[1 + 1:] }}", "llo", None)
+}
+
+#[test]
+fn error_index_out_of_range() -> Result<()> {
+    let result = assert_render_template_eq("{{ [0, 1, 2][3] }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Index out of range".to_string()
+    );
+    let result = assert_render_template_eq("{{ [0, 1, 2][-4] }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Index out of range".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_slice_zero_step() -> Result<()> {
+    let result = assert_render_template_eq("{{ [0, 1, 2][::0] }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid operation".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn lenient_undefined() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("user".to_string(), Value::ValuesMap(ValuesMap::default()));
+    assert_render_template_with_lenient_undefined_eq("[{{ [0, 1, 2][3] }}]", "[]", None)?;
+    assert_render_template_with_lenient_undefined_eq("[{{ unknown_value }}]", "[]", None)?;
+    assert_render_template_with_lenient_undefined_eq(
+        "[{{ user.name }}]",
+        "[]",
+        Some(context.clone()),
+    )?;
+    assert_render_template_with_lenient_undefined_eq(
+        "{{ user.name is defined }} {{ unknown_value is undefined }}",
+        "false true",
+        Some(context),
+    )
+}
+
+#[test]
+fn conditional_expressions() -> Result<()> {
+    assert_render_template_eq("{{ \"yes\" if 1 < 2 else \"no\" }}", "yes", None)?;
+    assert_render_template_eq("{{ \"yes\" if 1 > 2 else \"no\" }}", "no", None)?;
+    assert_render_template_eq("[{{ \"yes\" if false }}]", "[]", None)?;
+    assert_render_template_eq("{{ 1 if false else 2 if false else 3 }}", "3", None)?;
+    assert_render_template_eq("{{ (1 if true else 2) + 10 }}", "11", None)?;
+    assert_render_template_eq("{{ [1 if true else 2, 3] }}", "[1, 3]", None)
+}
+
+#[test]
+fn conditional_expressions_in_arguments_and_bindings() -> Result<()> {
+    assert_render_template_eq("{{ \"text\" | center(6 if true else 20) }}", " text ", None)?;
+    assert_render_template_eq(
+        "{% with x = \"a\" if false else \"b\" %}{{ x }}{% endwith %}",
+        "b",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% for x in [1, 2, 3] if x != 2 %}{{ x if x > 1 else \"one\" }}{% endfor %}",
+        "one3",
+        None,
+    )
+}
+
+#[test]
+fn membership_operators() -> Result<()> {
+    let mut user = ValuesMap::default();
+    user.insert(
+        "roles".to_string(),
+        Value::ValuesList(vec![
+            Value::String("admin".to_string()),
+            Value::String("editor".to_string()),
+        ]),
+    );
+    let mut context = ValuesMap::default();
+    context.insert("user".to_string(), Value::ValuesMap(user));
+
+    assert_render_template_eq(
+        "{{ \"admin\" in user.roles }}",
+        "true",
+        Some(context.clone()),
+    )?;
+    assert_render_template_eq(
+        "{{ \"guest\" in user.roles }}",
+        "false",
+        Some(context.clone()),
+    )?;
+    assert_render_template_eq("{{ \"roles\" in user }}", "true", Some(context.clone()))?;
+    assert_render_template_eq("{{ \"ell\" in \"hello\" }}", "true", None)?;
+    assert_render_template_eq("{{ 4 not in [1, 2, 3] }}", "true", None)?;
+    assert_render_template_eq("{{ \"h\" not in \"hello\" }}", "false", None)?;
+    assert_render_template_eq(
+        "{% if 'admin' in user.roles %}Welcome{% endif %}",
+        "Welcome",
+        Some(context),
+    )
+}
+
+#[test]
+fn logical_not_precedence() -> Result<()> {
+    assert_render_template_eq("{{ not 2 in [1, 2] }}", "false", None)?;
+    assert_render_template_eq("{{ not 1 == 2 }}", "true", None)?;
+    assert_render_template_eq("{{ not false and false }}", "false", None)?;
+    assert_render_template_eq("{{ not not true }}", "true", None)
+}
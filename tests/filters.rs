@@ -148,7 +148,16 @@ fn filter_escape() -> Result<()> {
         Some(context.clone()),
     )?;
     assert_render_template_eq("{{ ampersand | escape }}", "&amp;", Some(context.clone()))?;
-    assert_render_template_eq("{{ quotes | escape }}", "&#34;&#39;", Some(context.clone()))
+    assert_render_template_eq("{{ quotes | escape }}", "&#34;&#39;", Some(context.clone()))?;
+    assert_render_template_eq("{{ \"a < b\" | e }}", "a &lt; b", None)?;
+    assert_render_template_eq("{{ 42 | escape }}", "42", None)
+}
+
+#[test]
+fn filter_escape_safe_value() -> Result<()> {
+    assert_render_template_eq("{{ \"<b>\" | safe | escape }}", "<b>", None)?;
+    assert_render_template_eq("{{ \"<b>\" | safe | forceescape }}", "&lt;b&gt;", None)?;
+    assert_render_template_eq("{{ \"<b>\" | escape | escape }}", "&lt;b&gt;", None)
 }
 
 #[test]
@@ -193,3 +202,13 @@ fn round_filter() -> Result<()> {
     assert_render_template_eq("{{ 5.8 | round(method='floor') }}", "5.0", None)?;
     assert_render_template_eq("{{ 4.834 | round(precision=2) }}", "4.83", None)
 }
+
+#[test]
+fn filter_unpacked_params() -> Result<()> {
+    assert_render_template_eq("{{ \"text\" | center(*[6]) }}", " text ", None)?;
+    assert_render_template_eq(
+        "{{ \"text\" | center(**{\"width\": 8}) }}",
+        "  text  ",
+        None,
+    )
+}
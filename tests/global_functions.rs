@@ -0,0 +1,123 @@
+use temple::error::{Error, RenderErrorKind, Result};
+use temple::value::{Value, ValuesMap};
+use temple::{Template, TemplateEnv};
+
+fn functions_env<'a>() -> TemplateEnv<'a> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.add_function("url_for".to_string(), |args, _context| {
+        let name: String = args.get(0, "name")?;
+        let mut params: Vec<_> = args
+            .keyword
+            .iter()
+            .filter(|(key, _)| key.as_str() != "name")
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        params.sort();
+        if params.is_empty() {
+            Ok(Value::String(format!("/{}", name)))
+        } else {
+            Ok(Value::String(format!("/{}?{}", name, params.join("&"))))
+        }
+    });
+    temp_env.add_function("asset".to_string(), |args, context| {
+        let path: String = args.get(0, "path")?;
+        let version: Option<i64> = args.get_optional(1, "version")?;
+        let root = context.find("static_root")?;
+        match version {
+            Some(version) => Ok(Value::String(format!("{}/{}?v={}", root, path, version))),
+            None => Ok(Value::String(format!("{}/{}", root, path))),
+        }
+    });
+    temp_env.add_function("fail".to_string(), |_args, _context| {
+        Err(Error::from(RenderErrorKind::InvalidOperation))
+    });
+    temp_env.add_global("static_root".to_string(), "/static");
+    temp_env
+}
+
+fn assert_render_template_with_functions_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let temp_env = functions_env();
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let result = template.render_as_string(params.unwrap_or_default())?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn global_function() -> Result<()> {
+    assert_render_template_with_functions_eq("{{ url_for('index') }}", "/index", None)?;
+    assert_render_template_with_functions_eq(
+        "{{ url_for('user', id=42, tab='posts') }}",
+        "/user?id=42&tab=posts",
+        None,
+    )?;
+    assert_render_template_with_functions_eq(
+        "{{ url_for(name='user', **{'id': 7}) }}",
+        "/user?id=7",
+        None,
+    )
+}
+
+#[test]
+fn global_function_with_context() -> Result<()> {
+    assert_render_template_with_functions_eq(
+        "{{ asset('app.css') }} {{ asset('app.js', version=3) }}",
+        "/static/app.css /static/app.js?v=3",
+        None,
+    )?;
+    let mut context = ValuesMap::default();
+    context.insert("static_root".to_string(), Value::String("/cdn".to_string()));
+    assert_render_template_with_functions_eq(
+        "{{ asset('app.css') }}",
+        "/cdn/app.css",
+        Some(context),
+    )
+}
+
+#[test]
+fn global_function_in_expressions() -> Result<()> {
+    assert_render_template_with_functions_eq(
+        "{% set link = url_for('home') %}{{ link | upper }}",
+        "/HOME",
+        None,
+    )?;
+    assert_render_template_with_functions_eq(
+        "{% for page in ['a', 'b'] %}{{ url_for(page) }};{% endfor %}",
+        "/a;/b;",
+        None,
+    )
+}
+
+#[test]
+fn error_global_function() -> Result<()> {
+    let result = assert_render_template_with_functions_eq("{{ fail() }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid operation".to_string()
+    );
+    let result = assert_render_template_with_functions_eq("{{ url_for() }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Missing argument 'name'".to_string()
+    );
+    let result = assert_render_template_with_functions_eq("{{ asset(42) }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid type of the value in the particular context".to_string()
+    );
+    let result = assert_render_template_with_functions_eq(
+        "{{ url_for('user', id=1, **{'id': 2}) }}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Multiple values for argument 'id'".to_string()
+    );
+    Ok(())
+}
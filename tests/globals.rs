@@ -0,0 +1,167 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+use temple::{Template, TemplateEnv};
+
+#[test]
+fn global_range() -> Result<()> {
+    assert_render_template_eq("{{ range(4) }}", "[0, 1, 2, 3]", None)?;
+    assert_render_template_eq("{{ range(2, 5) }}", "[2, 3, 4]", None)?;
+    assert_render_template_eq("{{ range(10, 0, -3) }}", "[10, 7, 4, 1]", None)?;
+    assert_render_template_eq("{{ range(0) }}", "[]", None)?;
+    assert_render_template_eq("{% for i in range(1, 4) %}{{ i }}{% endfor %}", "123", None)
+}
+
+#[test]
+fn global_dict() -> Result<()> {
+    assert_render_template_eq("{{ dict(a=1, b='x') }}", "{\"a\": 1, \"b\": x}", None)?;
+    assert_render_template_eq("{{ dict(a=1)['a'] }}", "1", None)?;
+    assert_render_template_eq("{{ dict() | length }}", "0", None)
+}
+
+#[test]
+fn global_lipsum() -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.settings_mut().autoescape = true;
+    let mut template = Template::new(&temp_env)?;
+    template.load("{{ lipsum() }}")?;
+    let result = template.render_as_string(ValuesMap::default())?;
+    assert_eq!(result.matches("<p>").count(), 5);
+    assert!(result.starts_with("<p>") && result.ends_with(".</p>"));
+
+    template.load("{{ lipsum(2, html=false, min=5, max=10) }}")?;
+    let result = template.render_as_string(ValuesMap::default())?;
+    let paragraphs: Vec<&str> = result.split("\n\n").collect();
+    assert_eq!(paragraphs.len(), 2);
+    for paragraph in paragraphs {
+        let words = paragraph.split(' ').count();
+        assert!((5..10).contains(&words), "{}", paragraph);
+        assert!(paragraph.ends_with('.'));
+        assert!(paragraph.chars().next().unwrap().is_uppercase());
+    }
+    Ok(())
+}
+
+#[test]
+fn global_lipsum_is_reproducible() -> Result<()> {
+    let temp_env = TemplateEnv::default();
+    let mut template = Template::new(&temp_env)?;
+    template.load("{{ lipsum(3) }}|{{ lipsum(3) }}")?;
+    let first = template.render_as_string(ValuesMap::default())?;
+    let (left, right) = first.split_once('|').unwrap();
+    assert_eq!(left, right);
+    assert_eq!(template.render_as_string(ValuesMap::default())?, first);
+    Ok(())
+}
+
+#[test]
+fn global_cycler() -> Result<()> {
+    assert_render_template_eq(
+        "{% set row = cycler('odd', 'even') %}{% for i in range(3) %}{{ row.next() }} {% endfor %}{{ row.current }}",
+        "odd even odd even",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% set c = cycler(1, 2, 3) %}{{ c.current }}{{ c.next() }}{{ c.next() }}{{ c.reset() }}{{ c.next() }}",
+        "112None1",
+        None,
+    )
+}
+
+#[test]
+fn global_joiner() -> Result<()> {
+    assert_render_template_eq(
+        "{% set pipe = joiner(' | ') %}{% for item in ['a', 'b', 'c'] %}{{ pipe() }}{{ item }}{% endfor %}",
+        "a | b | c",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% set comma = joiner() %}{% for item in [1, 2] %}{{ comma() }}{{ item }}{% endfor %}",
+        "1, 2",
+        None,
+    )
+}
+
+#[test]
+fn global_namespace() -> Result<()> {
+    assert_render_template_eq(
+        "{% set ns = namespace(found=false) %}{% for item in [1, 2, 3] %}{% if item == 2 %}{% set ns.found = true %}{% endif %}{% endfor %}{{ ns.found }}",
+        "true",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% set ns = namespace(total=0) %}{% for item in [1, 2, 3] %}{% set ns.total = ns.total + item %}{% endfor %}{{ ns.total }}",
+        "6",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% set ns = namespace({'a': 1}, b=2) %}{% set ns.c %}3{% endset %}{{ ns.a }}{{ ns.b }}{{ ns.c }}",
+        "123",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% set ns = namespace() %}{% set other = ns %}{% set other.value = 'x' %}{{ ns.value }} {{ ns is sameas other }}",
+        "x true",
+        None,
+    )
+}
+
+#[test]
+fn global_overridden_by_context() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("range".to_string(), Value::String("mine".to_string()));
+    assert_render_template_eq("{{ range }}", "mine", Some(context))
+}
+
+#[test]
+fn error_global_range_zero_step() -> Result<()> {
+    let result = assert_render_template_eq("{{ range(0, 5, 0) }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid operation".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn global_range_near_integer_limits() -> Result<()> {
+    assert_render_template_eq(
+        "{{ range(9223372036854775806, 9223372036854775807, 5) }}",
+        "[9223372036854775806]",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{{ range(-9223372036854775807, -9223372036854775807 - 1, -2) }}",
+        "[-9223372036854775807]",
+        None,
+    )
+}
+
+#[test]
+fn global_lipsum_extreme_bounds() -> Result<()> {
+    assert_render_template_eq(
+        "{{ lipsum(1, False, -9223372036854775807, 9223372036854775807) }}",
+        ".",
+        None,
+    )
+}
+
+#[test]
+fn error_global_lipsum_min_above_max() -> Result<()> {
+    let result = assert_render_template_eq("{{ lipsum(1, min=10, max=5) }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid operation".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_set_attribute_on_non_namespace() -> Result<()> {
+    let result = assert_render_template_eq("{% set d = dict() %}{% set d.a = 1 %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid type of the value in the particular context".to_string()
+    );
+    Ok(())
+}
@@ -1,12 +1,29 @@
+mod autoescape;
 mod basic;
+mod custom_filters;
+mod custom_testers;
 mod error;
 mod expressions;
 mod filesystem_templates;
 mod filters;
+mod global_functions;
+mod globals;
+mod line_statements;
+mod malformed_templates;
+mod methods;
 mod scoped_context;
+mod statement_call;
+mod statement_do;
+mod statement_extends;
+mod statement_filter;
 mod statement_for;
 mod statement_if;
+mod statement_import;
 mod statement_include;
+mod statement_loop_controls;
+mod statement_macro;
+mod statement_set;
 mod statement_with;
+mod testers;
 mod utils;
 mod whitespace_control;
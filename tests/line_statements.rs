@@ -0,0 +1,118 @@
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+use temple::{Template, TemplateEnv};
+
+fn assert_render_template_with_line_statements_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.settings_mut().use_line_statements = true;
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let default_context = ValuesMap::default();
+    let context = params.unwrap_or(default_context);
+    let result = template.render_as_string(context)?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+fn items_context() -> ValuesMap {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "items".to_string(),
+        Value::ValuesList(vec![Value::Integer(1), Value::Integer(2)]),
+    );
+    context
+}
+
+#[test]
+fn line_statement_for() -> Result<()> {
+    assert_render_template_with_line_statements_eq(
+        "<ul>
+# for item in items
+  <li>{{ item }}</li>
+# endfor
+</ul>",
+        "<ul>
+  <li>1</li>
+  <li>2</li>
+</ul>",
+        Some(items_context()),
+    )
+}
+
+#[test]
+fn line_statement_indented_with_colon() -> Result<()> {
+    assert_render_template_with_line_statements_eq(
+        "  # if true:
+yes
+  # endif
+",
+        "yes
+",
+        None,
+    )
+}
+
+#[test]
+fn line_statement_at_the_end() -> Result<()> {
+    assert_render_template_with_line_statements_eq(
+        "# set x = 42
+{{ x }}
+# if x > 40
+big
+# endif",
+        "42
+big
+",
+        None,
+    )
+}
+
+#[test]
+fn line_comment() -> Result<()> {
+    assert_render_template_with_line_statements_eq(
+        "## This line is not rendered
+text ## trailing comment {{ ignored }}
+  ## indented comment
+end",
+        "\ntext \n\nend",
+        None,
+    )
+}
+
+#[test]
+fn line_statement_not_at_line_start() -> Result<()> {
+    assert_render_template_with_line_statements_eq("Item #1", "Item #1", None)
+}
+
+#[test]
+fn custom_line_prefixes() -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    let settings = temp_env.settings_mut();
+    settings.use_line_statements = true;
+    settings.line_statement_prefix = "%%".to_string();
+    settings.line_comment_prefix = "//".to_string();
+    let mut template = Template::new(&temp_env)?;
+    template.load(
+        "# Title // comment
+%% for item in items
+- {{ item }}
+%% endfor",
+    )?;
+    let result = template.render_as_string(items_context())?;
+    assert_eq!(result, "# Title \n- 1\n- 2\n".to_string());
+    Ok(())
+}
+
+#[test]
+fn line_statements_disabled() -> Result<()> {
+    let temp_env = TemplateEnv::default();
+    let mut template = Template::new(&temp_env)?;
+    template.load("# for item in items\n## comment")?;
+    let result = template.render_as_string(items_context())?;
+    assert_eq!(result, "# for item in items\n## comment".to_string());
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::ValuesMap;
+use temple::{Template, TemplateEnv};
+
+fn render_error(input: &str) -> String {
+    match assert_render_template_eq(input, "", None) {
+        Ok(()) => panic!("{:?} rendered without error", input),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[test]
+fn malformed_statements() {
+    assert_eq!(
+        render_error("{% %}"),
+        "noname.j2tpl:1:3: error: Specific token expected (statement)"
+    );
+    assert_eq!(
+        render_error("{% else %}{% endif %}"),
+        "noname.j2tpl: error: Unexpected statement"
+    );
+    assert_eq!(
+        render_error("{% for x in [] %}{% else %}{% endif %}"),
+        "noname.j2tpl: error: Unexpected statement"
+    );
+    for template in [
+        "a{% else %}b",
+        "a{% elif x %}b",
+        "{% for x in [] %}{% elif x %}{% endfor %}",
+        "{% if x %}{% else %}{% else %}{% endif %}",
+        "{% if x %}{% else %}{% elif x %}{% endif %}",
+        "{% with a = 1 %}{% else %}{% endwith %}",
+    ] {
+        assert_eq!(
+            render_error(template),
+            "noname.j2tpl: error: Unexpected statement",
+            "{}",
+            template
+        );
+    }
+    assert_eq!(
+        render_error("{{ 1 2 }}"),
+        "noname.j2tpl:1:5: error: Unexpected token"
+    );
+}
+
+#[test]
+fn errors_after_earlier_lines() {
+    assert_eq!(
+        render_error("{% for letter in word  -%}\n{%- for num \"first\"] }}\n{% endfor -%}"),
+        "noname.j2tpl:2:12: error: Specific token expected (in)"
+    );
+}
+
+#[test]
+fn unclosed_statements() {
+    for (template, expected) in [
+        (
+            "{% if true %}a",
+            "noname.j2tpl:1:2: error: Statement not closed, 'endif' expected",
+        ),
+        (
+            "{% if true %}a{% else %}b",
+            "noname.j2tpl:1:2: error: Statement not closed, 'endif' expected",
+        ),
+        (
+            "{% for x in [1] %}{{ x }}",
+            "noname.j2tpl:1:2: error: Statement not closed, 'endfor' expected",
+        ),
+        (
+            "a\n{% block b %}c",
+            "noname.j2tpl:2:2: error: Statement not closed, 'endblock' expected",
+        ),
+        (
+            "{% macro m() %}x",
+            "noname.j2tpl:1:2: error: Statement not closed, 'endmacro' expected",
+        ),
+        (
+            "{% set x %}y",
+            "noname.j2tpl:1:2: error: Statement not closed, 'endset' expected",
+        ),
+        (
+            "{% for x in [1] %}{% if x %}{% endfor %}",
+            "noname.j2tpl: error: Unexpected statement",
+        ),
+        (
+            "{% for x in [1] %}{% if x %}{% endif %}",
+            "noname.j2tpl:1:2: error: Statement not closed, 'endfor' expected",
+        ),
+    ] {
+        assert_eq!(render_error(template), expected, "{}", template);
+    }
+}
+
+#[test]
+fn unterminated_blocks() -> Result<()> {
+    assert!(render_error("{{-").ends_with("error: Expression expected"));
+    assert!(render_error("{{-}}").ends_with("error: Expression expected"));
+    assert!(render_error("{%-").ends_with("error: Specific token expected (statement)"));
+    assert_render_template_eq("{#-", "", None)
+}
+
+#[test]
+fn non_ascii_around_control_characters() -> Result<()> {
+    assert_render_template_eq("é {%- if true %}ü{% endif -%} \u{3000}é", "éüé", None)?;
+    assert_render_template_eq("{{ 'é' }}\u{a0}{{- 'ü' -}}\u{a0}", "éü", None)
+}
+
+#[test]
+fn else_branch_conditions() -> Result<()> {
+    assert_render_template_eq(
+        "{% if false %}a{% elif 'x' %}b{% else %}c{% endif %}",
+        "c",
+        None,
+    )?;
+    render_error("{% if false %}a{% elif unknown_value %}b{% endif %}");
+    Ok(())
+}
+
+#[test]
+fn empty_tuple() -> Result<()> {
+    assert_render_template_eq("{{ () }}", "[]", None)
+}
+
+#[test]
+fn integer_overflow() -> Result<()> {
+    for template in [
+        "{{ 9223372036854775807 + 1 }}",
+        "{{ 9223372036854775807 * 2 }}",
+        "{{ -(-9223372036854775807 - 1) }}",
+        "{{ 2 ** 64 }}",
+        "{{ 1 % 0 }}",
+        "{{ (-9223372036854775807 - 1) | abs }}",
+    ] {
+        assert_eq!(render_error(template), "Invalid operation", "{}", template);
+    }
+    assert_render_template_eq("{{ 2 ** -1 }}", "0.5", None)?;
+    assert_render_template_eq("{{ 'ab' * -1 }}", "", None)
+}
+
+#[test]
+fn invalid_operands() {
+    for template in [
+        "{{ 1 + 'a' }}",
+        "{{ -'a' }}",
+        "{{ 1 // 'a' }}",
+        "{{ 'a' in 3 }}",
+        "{{ 'a' not in 3 }}",
+    ] {
+        assert_eq!(render_error(template), "Invalid operation", "{}", template);
+    }
+}
+
+#[test]
+fn filters_on_edge_values() -> Result<()> {
+    assert_render_template_eq("{{ '' | capitalize }}", "", None)?;
+    assert_render_template_eq("{{ 'éa' | capitalize }}", "Éa", None)?;
+    assert_render_template_eq("{{ 'x' | center(-5) }}", "x", None)?;
+    assert_render_template_eq("{{ 'héllo' | truncate(2, end='') }}", "h", None)?;
+    assert_render_template_eq("{{ 'hello' | truncate(2) }}", "...", None)?;
+    assert_render_template_eq("{{ [] | first }}{{ '' | last }}{{ {} | max }}", "", None)?;
+    assert_render_template_eq("{{ [1.0, 0.0 / 0.0] | max }}", "NaN", None)?;
+    assert_render_template_eq("{{ [1, 'a'] | sum }}", "1.0", None)
+}
+
+#[test]
+fn nesting_too_deep() {
+    let expression = format!("{{{{ {}1{} }}}}", "(".repeat(1000), ")".repeat(1000));
+    assert!(render_error(&expression).ends_with("error: Maximum nesting depth exceeded"));
+
+    let expression = format!("{{{{ 1{} }}}}", " + 1".repeat(1000));
+    assert!(render_error(&expression).ends_with("error: Maximum nesting depth exceeded"));
+
+    let statements = format!(
+        "{}{}",
+        "{% if true %}".repeat(1000),
+        "{% endif %}".repeat(1000)
+    );
+    assert!(render_error(&statements).ends_with("error: Maximum nesting depth exceeded"));
+}
+
+#[test]
+fn endless_recursion() {
+    assert_eq!(
+        render_error("{% macro m() %}{{ m() }}{% endmacro %}{{ m() }}"),
+        "Maximum recursion depth exceeded"
+    );
+    assert_eq!(
+        render_error("{% for x in [[1]] recursive %}{{ loop([x]) }}{% endfor %}"),
+        "Maximum recursion depth exceeded"
+    );
+}
+
+#[test]
+fn value_too_deep() {
+    assert_eq!(
+        render_error("{% set ns = namespace(a=1) %}{% set ns.a = [ns] %}{{ ns }}"),
+        "Value nested too deeply"
+    );
+    assert_eq!(
+        render_error("{% set ns = namespace(a=[]) %}{{ ns.a.append(ns) }}{{ ns }}"),
+        "Value nested too deeply"
+    );
+    assert_eq!(
+        render_error(
+            "{% set ns = namespace(x=[]) %}{% for i in range(1000) %}{% set ns.x = [ns.x] %}\
+             {% endfor %}"
+        ),
+        "Value nested too deeply"
+    );
+}
+
+#[test]
+fn value_too_long() -> Result<()> {
+    for template in [
+        "{{ 'a' * 100000000000000 }}",
+        "{{ [1, 2] * 100000000000000 }}",
+        "{{ 'a' | center(100000000000000) }}",
+        "{{ ('a' * 1000).replace('a', 'b' * 10000) }}",
+        "{{ range(100000000000000) }}",
+        "{{ lipsum(100000000000000) }}",
+        "{{ lipsum(1, min=100000000000000, max=100000000000001) }}",
+    ] {
+        assert_eq!(render_error(template), "Value too long", "{}", template);
+    }
+    assert_render_template_eq("{{ [1, 2] * 2 }}{{ [1] * -1 }}", "[1, 2, 1, 2][]", None)
+}
+
+#[test]
+fn render_without_template() -> Result<()> {
+    let env = TemplateEnv::default();
+    let template = Template::new(&env)?;
+    assert_eq!(
+        template
+            .render_as_string(ValuesMap::default())
+            .unwrap_err()
+            .to_string(),
+        "Template not parsed"
+    );
+    Ok(())
+}
+
+#[test]
+fn params_which_are_not_a_map() -> Result<()> {
+    let env = TemplateEnv::default();
+    let mut template = Template::new(&env)?;
+    template.load("{{ x }}")?;
+    assert_eq!(
+        template.render_as_string(5).unwrap_err().to_string(),
+        "Invalid type of the value in the particular context"
+    );
+    Ok(())
+}
+
+#[test]
+fn stack_budget_setting() -> Result<()> {
+    let env = TemplateEnv::default();
+    let mut template = Template::new(&env)?;
+    template.load(
+        "{% macro m(n) %}{% if n > 0 %}{{ m(n - 1) }}{% endif %}{% endmacro %}{{ m(20) }}.",
+    )?;
+    assert_eq!(template.render_as_string(ValuesMap::default())?, ".");
+
+    // Without any stack budget, neither parsing an expression nor rendering succeeds
+    let mut env = TemplateEnv::default();
+    env.settings_mut().stack_budget = 0;
+    let mut template = Template::new(&env)?;
+    assert!(template
+        .load("{{ 1 }}")
+        .unwrap_err()
+        .to_string()
+        .ends_with("error: Maximum nesting depth exceeded"));
+    let mut template = Template::new(&env)?;
+    template.load("text")?;
+    assert_eq!(
+        template
+            .render_as_string(ValuesMap::default())
+            .unwrap_err()
+            .to_string(),
+        "Maximum recursion depth exceeded"
+    );
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use super::utils::{assert_render_template_eq, assert_render_template_with_do_eq};
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+
+#[test]
+fn string_methods() -> Result<()> {
+    assert_render_template_eq("{{ 'Hello'.upper() }}", "HELLO", None)?;
+    assert_render_template_eq("{{ 'Hello'.lower() }}", "hello", None)?;
+    assert_render_template_eq("[{{ '  text \n'.strip() }}]", "[text]", None)?;
+    assert_render_template_eq("{{ '--text--'.strip('-') }}", "text", None)?;
+    assert_render_template_eq("{{ 'temple'.startswith('tem') }}", "true", None)?;
+    assert_render_template_eq("{{ 'temple'.endswith(['x', 'ple']) }}", "true", None)?;
+    assert_render_template_eq("{{ 'temple'.endswith('tem') }}", "false", None)?;
+    assert_render_template_eq("{{ 'a-b-a'.replace('a', 'c') }}", "c-b-c", None)?;
+    assert_render_template_eq("{{ 'a-b-a'.replace('a', 'c', 1) }}", "c-b-a", None)?;
+    assert_render_template_eq("{{ ', '.join(['a', 'b', 3]) }}", "a, b, 3", None)
+}
+
+#[test]
+fn string_split() -> Result<()> {
+    assert_render_template_eq("{{ 'a,b,,c'.split(',') }}", "[a, b, , c]", None)?;
+    assert_render_template_eq("{{ ' a  b c '.split() }}", "[a, b, c]", None)?;
+    assert_render_template_eq("{{ 'a,b,c'.split(',', 1) }}", "[a, b,c]", None)?;
+    assert_render_template_eq("{{ 'a b  c d'.split(None, 2) }}", "[a, b, c d]", None)?;
+    assert_render_template_eq(
+        "{% for part in 'x;y'.split(';') %}<{{ part }}>{% endfor %}",
+        "<x><y>",
+        None,
+    )
+}
+
+#[test]
+fn string_format() -> Result<()> {
+    assert_render_template_eq("{{ '{} and {}'.format(1, 'b') }}", "1 and b", None)?;
+    assert_render_template_eq("{{ '{1}{0}{1}'.format('a', 'b') }}", "bab", None)?;
+    assert_render_template_eq(
+        "{{ 'Hello, {name}!'.format(name='World') }}",
+        "Hello, World!",
+        None,
+    )?;
+    let mut context = ValuesMap::default();
+    context.insert("pattern".to_string(), Value::String("{{{}}}".to_string()));
+    assert_render_template_eq("{{ pattern.format(42) }}", "{42}", Some(context))
+}
+
+#[test]
+fn dict_methods() -> Result<()> {
+    let mut map = ValuesMap::default();
+    map.insert("a".to_string(), Value::Integer(1));
+    map.insert("b".to_string(), Value::Integer(2));
+    let mut context = ValuesMap::default();
+    context.insert("map".to_string(), Value::ValuesMap(map));
+    assert_render_template_eq(
+        "{% for key, value in map.items() %}{{ key }}={{ value }};{% endfor %}",
+        "a=1;b=2;",
+        Some(context.clone()),
+    )?;
+    assert_render_template_eq("{{ map.keys() }}", "[a, b]", Some(context.clone()))?;
+    assert_render_template_eq("{{ map.values() }}", "[1, 2]", Some(context.clone()))?;
+    assert_render_template_eq(
+        "{{ map.get('a') }} {{ map.get('c') }} {{ map.get('c', 0) }}",
+        "1 None 0",
+        Some(context),
+    )
+}
+
+#[test]
+fn list_methods() -> Result<()> {
+    assert_render_template_eq("{{ ['a', 'b', 'c'].index('b') }}", "1", None)?;
+    assert_render_template_eq("{{ [1, 2, 1, 1].count(1) }}", "3", None)
+}
+
+#[test]
+fn list_append_and_extend() -> Result<()> {
+    assert_render_template_with_do_eq(
+        "{% set items = [1] %}{% do items.append(2) %}{% do items.extend([3, 4]) %}{{ items }}",
+        "[1, 2, 3, 4]",
+        None,
+    )?;
+    assert_render_template_with_do_eq(
+        "{% set found = [] %}{% for x in range(5) %}{% if x is odd %}{% do found.append(x) %}{% endif %}{% endfor %}{{ found }}",
+        "[1, 3]",
+        None,
+    )?;
+    assert_render_template_with_do_eq(
+        "{% set ns = namespace(items=[]) %}{% for x in 'ab' %}{% do ns.items.append(x) %}{% endfor %}{{ ns.items }}",
+        "[a, b]",
+        None,
+    )
+}
+
+#[test]
+fn list_append_inside_containers() -> Result<()> {
+    assert_render_template_with_do_eq(
+        "{% set d = {'l': [1]} %}{% do d.l.append(2) %}{{ d.l }}",
+        "[1, 2]",
+        None,
+    )?;
+    assert_render_template_with_do_eq(
+        "{% set rows = [[1], [2]] %}{% do rows[-1].extend([3]) %}{{ rows }}",
+        "[[1], [2, 3]]",
+        None,
+    )?;
+    assert_render_template_with_do_eq(
+        "{% set ns = namespace(d={'l': []}) %}{% do ns.d.l.append(1) %}{{ ns.d.l }}",
+        "[1]",
+        None,
+    )?;
+    assert_render_template_with_do_eq(
+        "{% set items = [1] %}{% do items[:].append(2) %}{{ items }}",
+        "[1]",
+        None,
+    )
+}
+
+#[test]
+fn list_append_to_render_parameter() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "items".to_string(),
+        Value::ValuesList(vec![Value::Integer(1)]),
+    );
+    assert_render_template_with_do_eq(
+        "{% do items.append(2) %}{{ items }}",
+        "[1, 2]",
+        Some(context),
+    )
+}
+
+#[test]
+fn methods_do_not_hide_map_callables() -> Result<()> {
+    assert_render_template_eq(
+        "{% for x in [1, 2] %}{{ loop.cycle('a', 'b') }}{% endfor %}",
+        "ab",
+        None,
+    )
+}
+
+#[test]
+fn methods_take_precedence_over_map_keys() -> Result<()> {
+    assert_render_template_eq(
+        "{% set order = {'items': [1, 2], 'id': 7} %}{{ order.items() | length }} {{ order['items'] }}",
+        "2 [1, 2]",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% macro get() %}mine{% endmacro %}{% set m = {'get': get} %}{{ m['get']() }} {{ m.get('get') is defined }}",
+        "mine true",
+        None,
+    )
+}
+
+#[test]
+fn error_list_index_not_found() -> Result<()> {
+    let result = assert_render_template_eq("{{ [1, 2].index(3) }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid operation".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn string_format_spec() -> Result<()> {
+    assert_render_template_eq("{{ '{:>10}'.format(1) }}", "         1", None)?;
+    assert_render_template_eq("{{ '{:<5}|'.format('ab') }}", "ab   |", None)?;
+    assert_render_template_eq("{{ '{:*^7}'.format('ab') }}", "**ab***", None)?;
+    assert_render_template_eq("{{ '{:05d}'.format(-42) }}", "-0042", None)?;
+    assert_render_template_eq("{{ '{:+.2f}'.format(3.14159) }}", "+3.14", None)?;
+    assert_render_template_eq(
+        "{{ '{0:b} {0:o} {0:x} {0:X}'.format(255) }}",
+        "11111111 377 ff FF",
+        None,
+    )?;
+    assert_render_template_eq("{{ '{name:.3}'.format(name='abcdef') }}", "abc", None)
+}
+
+#[test]
+fn error_string_format_unsupported_spec() {
+    for template in [
+        "{{ '{:#x}'.format(1) }}",
+        "{{ '{:d}'.format('a') }}",
+        "{{ '{:.2}'.format(1.5) }}",
+        "{{ '{:+}'.format('a') }}",
+    ] {
+        let result = assert_render_template_eq(template, "", None);
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Invalid operation".to_string(),
+            "{}",
+            template
+        );
+    }
+    let result = assert_render_template_eq("{{ '{:99999999999}'.format(1) }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Value too long".to_string()
+    );
+}
+
+#[test]
+fn error_string_format_missing_argument() -> Result<()> {
+    let result = assert_render_template_eq("{{ '{} {}'.format(1) }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Missing argument '1'".to_string()
+    );
+    Ok(())
+}
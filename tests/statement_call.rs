@@ -0,0 +1,63 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+
+#[test]
+fn call_simple() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro wrap() %}<div>{{ caller() }}</div>{% endmacro %}\
+{% call wrap() %}Content{% endcall %}",
+        "<div>Content</div>",
+        None,
+    )
+}
+
+#[test]
+fn call_with_macro_params() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro dialog(title, class=\"dialog\") %}[{{ class }}:{{ title }}]{{ caller() }}{% endmacro %}\
+{% call dialog(\"Hello\") %}Body{% endcall %}",
+        "[dialog:Hello]Body",
+        None,
+    )
+}
+
+#[test]
+fn call_with_caller_params() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "users".to_string(),
+        Value::ValuesList(vec![
+            Value::String("alice".to_string()),
+            Value::String("bob".to_string()),
+        ]),
+    );
+    assert_render_template_eq(
+        "{% macro render_list(items) %}<ul>{% for item in items %}<li>{{ caller(item) }}</li>{% endfor %}</ul>{% endmacro %}\
+{% call(user) render_list(users) %}{{ user }}!{% endcall %}",
+        "<ul><li>alice!</li><li>bob!</li></ul>",
+        Some(context),
+    )
+}
+
+#[test]
+fn call_body_sees_outer_scope() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("name".to_string(), Value::String("outer".to_string()));
+    assert_render_template_eq(
+        "{% macro twice() %}{{ caller() }}{{ caller() }}{% endmacro %}\
+{% call twice() %}{{ name }};{% endcall %}",
+        "outer;outer;",
+        Some(context),
+    )
+}
+
+#[test]
+fn error_call_without_call_expression() -> Result<()> {
+    let result = assert_render_template_eq("{% call wrap %}{% endcall %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:13: error: '(' expected".to_string()
+    );
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use super::utils::{assert_render_template_eq, assert_render_template_with_do_eq};
+use temple::error::Result;
+
+#[test]
+fn do_discards_result() -> Result<()> {
+    assert_render_template_with_do_eq("[{% do 40 + 2 %}]", "[]", None)
+}
+
+#[test]
+fn do_evaluates_expression() -> Result<()> {
+    let result = assert_render_template_with_do_eq("{% do undefined_value %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: undefined_value is not defined.".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_do_extension_disabled() -> Result<()> {
+    let result = assert_render_template_eq("Hello{% do 1 %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:8: error: Extension disabled.".to_string()
+    );
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+use temple::{MemoryFileSystem, Template, TemplateEnv};
+
+fn assert_render_template_with_parents_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+
+    let mut handler = MemoryFileSystem::new();
+    handler.add_file(
+        "base.j2".to_string(),
+        "<{% block title %}Base{% endblock %}>[{% block body %}{% endblock body %}]".to_string(),
+    );
+    handler.add_file(
+        "child.j2".to_string(),
+        "{% extends \"base.j2\" %}{% block title %}Child{% endblock %}".to_string(),
+    );
+    handler.add_file(
+        "nested.j2".to_string(),
+        "{% block outer %}({% block inner %}inner{% endblock %}){% endblock %}".to_string(),
+    );
+    handler.add_file(
+        "loop.j2".to_string(),
+        "{% for item in items %}{% block item scoped %}{% endblock %}{% endfor %}".to_string(),
+    );
+    handler.add_file(
+        "required.j2".to_string(),
+        "{% block content required %}{% endblock %}".to_string(),
+    );
+    temp_env.add_filesystem_handler(Box::new(handler))?;
+
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let default_context = ValuesMap::default();
+    let context = params.unwrap_or(default_context);
+    let result = template.render_as_string(context)?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn render_blocks_without_extends() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% block title %}Hello{% endblock %} world",
+        "Hello world",
+        None,
+    )
+}
+
+#[test]
+fn override_block() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% extends \"base.j2\" %}{% block body %}Content{% endblock %}",
+        "<Base>[Content]",
+        None,
+    )
+}
+
+#[test]
+fn content_outside_blocks_is_ignored() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% extends \"base.j2\" %}Ignored{% block body %}Content{% endblock %}Ignored",
+        "<Base>[Content]",
+        None,
+    )
+}
+
+#[test]
+fn call_super_block() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% extends \"base.j2\" %}{% block title %}{{ super() }}!{% endblock %}",
+        "<Base!>[]",
+        None,
+    )
+}
+
+#[test]
+fn multiple_levels_of_inheritance() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% extends \"child.j2\" %}{% block title %}{{ super() }}+{% endblock %}",
+        "<Child+>[]",
+        None,
+    )
+}
+
+#[test]
+fn override_nested_block() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% extends \"nested.j2\" %}{% block inner %}{{ super() }}-child{% endblock %}",
+        "(inner-child)",
+        None,
+    )
+}
+
+#[test]
+fn call_self_block() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% extends \"base.j2\" %}{% block body %}{{ self.title() }}{% endblock %}",
+        "<Base>[Base]",
+        None,
+    )
+}
+
+#[test]
+fn scoped_block() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "items".to_string(),
+        Value::ValuesList(vec![Value::Integer(1), Value::Integer(2)]),
+    );
+    assert_render_template_with_parents_eq(
+        "{% extends \"loop.j2\" %}{% block item %}{{ item }}{% endblock %}",
+        "12",
+        Some(context),
+    )
+}
+
+#[test]
+fn unscoped_block() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "items".to_string(),
+        Value::ValuesList(vec![Value::Integer(1), Value::Integer(2)]),
+    );
+    assert_render_template_with_parents_eq(
+        "{% for item in items %}{% block item %}{{ item }}{% endblock %}{% endfor %}",
+        "",
+        Some(context),
+    )
+    .unwrap_err();
+    Ok(())
+}
+
+#[test]
+fn override_required_block() -> Result<()> {
+    assert_render_template_with_parents_eq(
+        "{% extends \"required.j2\" %}{% block content %}Content{% endblock %}",
+        "Content",
+        None,
+    )
+}
+
+#[test]
+fn error_required_block_not_overridden() -> Result<()> {
+    let result = assert_render_template_with_parents_eq("{% extends \"required.j2\" %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Required block 'content' not found".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_missing_parent() -> Result<()> {
+    let result = assert_render_template_with_parents_eq("{% extends \"missing.j2\" %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: Template missing.j2 not found.".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_duplicate_block() -> Result<()> {
+    let result = assert_render_template_with_parents_eq(
+        "{% block title %}{% endblock %}{% block title %}{% endblock %}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:60: error: Block 'title' defined twice".to_string()
+    );
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+
+#[test]
+fn filter_block_simple() -> Result<()> {
+    assert_render_template_eq(
+        "{% filter upper %}Hello {{ \"world\" }}{% endfilter %}!",
+        "HELLO WORLD!",
+        None,
+    )
+}
+
+#[test]
+fn filter_block_chain_with_params() -> Result<()> {
+    assert_render_template_eq(
+        "{% filter upper | truncate(8) %}This text will be truncated{% endfilter %}",
+        "THIS ...",
+        None,
+    )
+}
+
+#[test]
+fn filter_block_with_statements() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "items".to_string(),
+        Value::ValuesList(vec![
+            Value::String("a".to_string()),
+            Value::String("b".to_string()),
+        ]),
+    );
+    assert_render_template_eq(
+        "{% filter capitalize %}{% for item in items %}{{ item }}{% endfor %}{% endfilter %}",
+        "Ab",
+        Some(context),
+    )
+}
+
+#[test]
+fn error_filter_block_unknown_filter() -> Result<()> {
+    let result = assert_render_template_eq("{% filter unknown %}{% endfilter %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: Unknown filter: unknown".to_string()
+    );
+    Ok(())
+}
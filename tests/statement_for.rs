@@ -50,3 +50,267 @@ false
         Some(context),
     )
 }
+
+#[test]
+fn for_else_on_empty_iterable() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("items".to_string(), Value::ValuesList(vec![]));
+    assert_render_template_eq(
+        "{% for item in items %}{{ item }}{% else %}No items{% endfor %}",
+        "No items",
+        Some(context),
+    )
+}
+
+#[test]
+fn for_else_not_rendered() -> Result<()> {
+    assert_render_template_eq(
+        "{% for item in [1, 2] %}{{ item }}{% else %}No items{% endfor %}",
+        "12",
+        None,
+    )
+}
+
+#[test]
+fn for_with_condition() -> Result<()> {
+    assert_render_template_eq(
+        "{% for num in [1, 2, 3, 4, 5, 6] if num % 2 == 0 %}{{ loop.index }}/{{ loop.length }}:{{ num }} {% endfor %}",
+        "1/3:2 2/3:4 3/3:6 ",
+        None,
+    )
+}
+
+#[test]
+fn for_with_condition_and_else() -> Result<()> {
+    assert_render_template_eq(
+        "{% for num in [1, 3, 5] if num > 10 %}{{ num }}{% else %}None matched{% endfor %}",
+        "None matched",
+        None,
+    )
+}
+
+#[test]
+fn for_else_with_inner_if() -> Result<()> {
+    assert_render_template_eq(
+        "{% for num in [1, 2] %}{% if num == 1 %}one{% else %}other{% endif %}{% else %}empty{% endfor %}",
+        "oneother",
+        None,
+    )
+}
+
+#[test]
+fn error_for_with_elif() -> Result<()> {
+    let result = assert_render_template_eq(
+        "{% for num in [1, 2] %}{{ num }}{% elif true %}{% endfor %}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: Unexpected statement".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn for_tuple_unpacking() -> Result<()> {
+    assert_render_template_eq(
+        "{% for key, value in [[\"a\", 1], [\"b\", 2]] %}{{ key }}={{ value }};{% endfor %}",
+        "a=1;b=2;",
+        None,
+    )
+}
+
+#[test]
+fn for_nested_tuple_unpacking() -> Result<()> {
+    assert_render_template_eq(
+        "{% for name, (x, y) in [[\"p\", [1, 2]], [\"q\", [3, 4]]] %}{{ name }}({{ x }},{{ y }}) {% endfor %}",
+        "p(1,2) q(3,4) ",
+        None,
+    )
+}
+
+#[test]
+fn error_for_unpack_mismatch() -> Result<()> {
+    let result = assert_render_template_eq(
+        "{% for a, b in [[1, 2], [3, 4, 5]] %}{{ a }}{% endfor %}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Cannot unpack 3 values into 2 variables".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_for_over_non_iterable() {
+    for template in [
+        "{% for a, b in 3 %}{{ a }}{% endfor %}",
+        "{% for a, b in [3] %}{{ a }}{% endfor %}",
+        "{% for x in 3 %}{{ x }}{% endfor %}",
+        "{% set a, b = 3 %}",
+    ] {
+        let result = assert_render_template_eq(template, "", None);
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Invalid type of the value in the particular context".to_string(),
+            "{}",
+            template
+        );
+    }
+}
+
+fn tree_context() -> ValuesMap {
+    let node = |name: &str, children: Vec<Value>| {
+        let mut node = ValuesMap::default();
+        node.insert("name".to_string(), Value::String(name.to_string()));
+        node.insert("children".to_string(), Value::ValuesList(children));
+        Value::ValuesMap(node)
+    };
+    let mut context = ValuesMap::default();
+    context.insert(
+        "tree".to_string(),
+        Value::ValuesList(vec![
+            node(
+                "a",
+                vec![node("a1", vec![node("a1x", vec![])]), node("a2", vec![])],
+            ),
+            node("b", vec![]),
+        ]),
+    );
+    context
+}
+
+#[test]
+fn recursive_for() -> Result<()> {
+    assert_render_template_eq(
+        "{% for node in tree recursive %}[{{ node.name }}{{ loop(node.children) }}]{% endfor %}",
+        "[a[a1[a1x]][a2]][b]",
+        Some(tree_context()),
+    )
+}
+
+#[test]
+fn recursive_for_depth() -> Result<()> {
+    assert_render_template_eq(
+        "{% for node in tree recursive %}{{ node.name }}:{{ loop.depth }}/{{ loop.depth0 }} {{ loop(node.children) }}{% endfor %}",
+        "a:1/0 a1:2/1 a1x:3/2 a2:2/1 b:1/0 ",
+        Some(tree_context()),
+    )
+}
+
+#[test]
+fn recursive_for_with_condition() -> Result<()> {
+    assert_render_template_eq(
+        "{% for node in tree if node.name != \"a2\" recursive %}{{ node.name }};{{ loop(node.children) }}{% endfor %}",
+        "a;a1;a1x;b;",
+        Some(tree_context()),
+    )
+}
+
+#[test]
+fn error_call_loop_without_recursive() -> Result<()> {
+    let result = assert_render_template_eq(
+        "{% for node in tree %}{{ loop(node.children) }}{% endfor %}",
+        "",
+        Some(tree_context()),
+    );
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn recursive_loop_hides_its_call() -> Result<()> {
+    assert_render_template_eq(
+        "{% for node in tree recursive %}{{ loop }} {{ loop.__call__ is defined }} {% endfor %}",
+        "<callable 'loop'> false <callable 'loop'> false ",
+        Some(tree_context()),
+    )
+}
+
+#[test]
+fn error_call_map_with_call_key() -> Result<()> {
+    let result =
+        assert_render_template_eq("{% set d = {\"__call__\": range} %}{{ d(2) }}", "", None);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn loop_reverse_indices() -> Result<()> {
+    assert_render_template_eq(
+        "{% for item in \"abc\" %}{{ loop.revindex }}{{ loop.revindex0 }}{{ loop.length }} {% endfor %}",
+        "323 213 103 ",
+        None,
+    )
+}
+
+#[test]
+fn loop_previtem_nextitem() -> Result<()> {
+    assert_render_template_eq(
+        "{% for item in [1, 2, 3] %}{% if not loop.first %}<{{ loop.previtem }}{% endif %}[{{ item }}]{% if not loop.last %}{{ loop.nextitem }}>{% endif %}{% endfor %}",
+        "[1]2><1[2]3><2[3]",
+        None,
+    )
+}
+
+#[test]
+fn loop_previtem_nextitem_undefined_at_ends() -> Result<()> {
+    assert_render_template_eq(
+        "{% for item in [1, 2] %}{{ loop.previtem is defined }}/{{ loop.nextitem is defined }} {% endfor %}",
+        "false/true true/false ",
+        None,
+    )?;
+    assert_render_template_eq(
+        "{% for item in [1] %}<{{ loop.previtem }}|{{ loop.nextitem }}>{% endfor %}",
+        "<|>",
+        None,
+    )
+}
+
+#[test]
+fn loop_cycle() -> Result<()> {
+    assert_render_template_eq(
+        "{% for item in [1, 2, 3, 4, 5] %}{{ loop.cycle(\"odd\", \"even\", \"third\") }} {% endfor %}",
+        "odd even third odd even ",
+        None,
+    )
+}
+
+#[test]
+fn loop_changed() -> Result<()> {
+    let entry = |group: &str, name: &str| {
+        let mut entry = ValuesMap::default();
+        entry.insert("group".to_string(), Value::String(group.to_string()));
+        entry.insert("name".to_string(), Value::String(name.to_string()));
+        Value::ValuesMap(entry)
+    };
+    let mut context = ValuesMap::default();
+    context.insert(
+        "entries".to_string(),
+        Value::ValuesList(vec![
+            entry("A", "a1"),
+            entry("A", "a2"),
+            entry("B", "b1"),
+            entry("A", "a3"),
+        ]),
+    );
+    assert_render_template_eq(
+        "{% for entry in entries %}{% if loop.changed(entry.group) %}#{{ entry.group }} {% endif %}{{ entry.name }} {% endfor %}",
+        "#A a1 a2 #B b1 #A a3 ",
+        Some(context),
+    )
+}
+
+#[test]
+fn error_loop_cycle_without_items() -> Result<()> {
+    let result = assert_render_template_eq(
+        "{% for item in [1] %}{{ loop.cycle() }}{% endfor %}",
+        "",
+        None,
+    );
+    assert!(result.is_err());
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+use temple::{MemoryFileSystem, Template, TemplateEnv};
+
+fn assert_render_template_with_imports_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+
+    let mut handler = MemoryFileSystem::new();
+    handler.add_file(
+        "macros.j2".to_string(),
+        "{% macro hello(name) %}Hello {{ name }}!{% endmacro %}\
+{% macro bye(name) %}Bye {{ name }}!{% endmacro %}\
+{% with unused = 1 %}{% endwith %}Ignored output"
+            .to_string(),
+    );
+    handler.add_file(
+        "greeter.j2".to_string(),
+        "{% macro greet() %}Hello {{ user }}!{% endmacro %}".to_string(),
+    );
+    temp_env.add_filesystem_handler(Box::new(handler))?;
+
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let default_context = ValuesMap::default();
+    let context = params.unwrap_or(default_context);
+    let result = template.render_as_string(context)?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn import_as_module() -> Result<()> {
+    assert_render_template_with_imports_eq(
+        "{% import \"macros.j2\" as macros %}{{ macros.hello(\"world\") }} {{ macros.bye(\"world\") }}",
+        "Hello world! Bye world!",
+        None,
+    )
+}
+
+#[test]
+fn from_import() -> Result<()> {
+    assert_render_template_with_imports_eq(
+        "{% from \"macros.j2\" import hello, bye as goodbye %}{{ hello(\"a\") }} {{ goodbye(\"b\") }}",
+        "Hello a! Bye b!",
+        None,
+    )
+}
+
+#[test]
+fn import_with_context() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("user".to_string(), Value::String("admin".to_string()));
+    assert_render_template_with_imports_eq(
+        "{% from \"greeter.j2\" import greet with context %}{{ greet() }}",
+        "Hello admin!",
+        Some(context),
+    )
+}
+
+#[test]
+fn import_without_context() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("user".to_string(), Value::String("admin".to_string()));
+    let result = assert_render_template_with_imports_eq(
+        "{% import \"greeter.j2\" as greeter without context %}{{ greeter.greet() }}",
+        "",
+        Some(context),
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: user is not defined.".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_import_missing_name() -> Result<()> {
+    let result =
+        assert_render_template_with_imports_eq("{% from \"macros.j2\" import unknown %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: unknown is not defined.".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_import_missing_alias() -> Result<()> {
+    let result = assert_render_template_with_imports_eq("{% import \"macros.j2\" %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:22: error: Specific token expected (as)".to_string()
+    );
+    Ok(())
+}
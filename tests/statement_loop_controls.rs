@@ -0,0 +1,140 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::ValuesMap;
+use temple::{MemoryFileSystem, Template, TemplateEnv};
+
+fn assert_render_template_with_loop_controls_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.settings_mut().extensions.loop_controls = true;
+
+    let mut handler = MemoryFileSystem::new();
+    handler.add_file("item.j2".to_string(), "<{{ item }}>".to_string());
+    handler.add_file("break.j2".to_string(), "{% break %}".to_string());
+    temp_env.add_filesystem_handler(Box::new(handler))?;
+
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let default_context = ValuesMap::default();
+    let context = params.unwrap_or(default_context);
+    let result = template.render_as_string(context)?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn loop_break() -> Result<()> {
+    assert_render_template_with_loop_controls_eq(
+        "{% for item in [1, 2, 3, 4] %}{{ item }}{% if item == 2 %}{% break %}{% endif %}!{% endfor %}",
+        "1!2",
+        None,
+    )
+}
+
+#[test]
+fn loop_continue() -> Result<()> {
+    assert_render_template_with_loop_controls_eq(
+        "{% for item in [1, 2, 3, 4] %}{% if item % 2 == 0 %}{% continue %}{% endif %}{{ item }}{% endfor %}",
+        "13",
+        None,
+    )
+}
+
+#[test]
+fn loop_break_inside_with() -> Result<()> {
+    assert_render_template_with_loop_controls_eq(
+        "{% for item in [1, 2, 3] %}{% with double = item * 2 %}{{ double }}{% if double > 2 %}{% break %}{% endif %};{% endwith %}{% endfor %}",
+        "2;4",
+        None,
+    )
+}
+
+#[test]
+fn loop_continue_after_include() -> Result<()> {
+    assert_render_template_with_loop_controls_eq(
+        "{% for item in [1, 2, 3] %}{% include \"item.j2\" %}{% if item == 2 %}{% continue %}{% endif %}.{% endfor %}",
+        "<1>.<2><3>.",
+        None,
+    )
+}
+
+#[test]
+fn loop_break_inner_loop_only() -> Result<()> {
+    assert_render_template_with_loop_controls_eq(
+        "{% for row in [1, 2] %}{% for col in [1, 2, 3] %}{% if col > row %}{% break %}{% endif %}{{ row }}{{ col }} {% endfor %}| {% endfor %}",
+        "11 | 21 22 | ",
+        None,
+    )
+}
+
+#[test]
+fn loop_break_keeps_else_unrendered() -> Result<()> {
+    assert_render_template_with_loop_controls_eq(
+        "{% for item in [1, 2] %}{% break %}{% else %}empty{% endfor %}done",
+        "done",
+        None,
+    )
+}
+
+#[test]
+fn error_break_outside_loop() -> Result<()> {
+    let result = assert_render_template_with_loop_controls_eq("Hello {% break %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:9: error: 'break' used outside of a loop".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_continue_in_loop_else() -> Result<()> {
+    let result = assert_render_template_with_loop_controls_eq(
+        "{% for item in [] %}{% else %}{% continue %}{% endfor %}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:33: error: 'continue' used outside of a loop".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_break_in_macro_inside_loop() -> Result<()> {
+    let result = assert_render_template_with_loop_controls_eq(
+        "{% for item in [1] %}{% macro m() %}{% break %}{% endmacro %}{% endfor %}",
+        "",
+        None,
+    );
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn error_break_in_included_template() -> Result<()> {
+    let result = assert_render_template_with_loop_controls_eq(
+        "{% for item in [1, 2] %}{% include \"break.j2\" %}{% endfor %}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "break.j2:1:3: error: 'break' used outside of a loop".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_loop_controls_disabled() -> Result<()> {
+    let result =
+        assert_render_template_eq("{% for item in [1] %}{% break %}{% endfor %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:24: error: Extension disabled.".to_string()
+    );
+    Ok(())
+}
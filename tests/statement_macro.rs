@@ -0,0 +1,201 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+
+#[test]
+fn macro_simple() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro hello() %}Hello World!{% endmacro %}{{ hello() }}",
+        "Hello World!",
+        None,
+    )
+}
+
+#[test]
+fn macro_positional_params() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("name".to_string(), Value::String("Jinja".to_string()));
+    assert_render_template_eq(
+        "{% macro greet(greeting, name) %}{{ greeting }}, {{ name }}!{% endmacro %}{{ greet(\"Hello\", name) }}",
+        "Hello, Jinja!",
+        Some(context),
+    )
+}
+
+#[test]
+fn macro_default_params() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro input(name, type=\"text\", size=20) %}{{ name }}|{{ type }}|{{ size }}{% endmacro %}\
+{{ input(\"user\") }} {{ input(\"pass\", \"password\") }} {{ input(\"age\", size=3) }}",
+        "user|text|20 pass|password|20 age|text|3",
+        None,
+    )
+}
+
+#[test]
+fn macro_varargs() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro list(first) %}{{ first }}{% for arg in varargs %},{{ arg }}{% endfor %}{% endmacro %}{{ list(1, 2, 3) }}",
+        "1,2,3",
+        None,
+    )
+}
+
+#[test]
+fn macro_kwargs() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro attrs() %}{{ kwargs.id }}-{{ kwargs.class }}{% endmacro %}{{ attrs(id=\"main\", class=\"wide\") }}",
+        "main-wide",
+        None,
+    )
+}
+
+#[test]
+fn macro_is_a_value() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro twice(value) %}{{ value }}{{ value }}{% endmacro %}\
+{% with fn = twice %}{{ fn(\"ab\") }}{% endwith %}",
+        "abab",
+        None,
+    )
+}
+
+#[test]
+fn macro_recursive() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro countdown(n) %}{{ n }}{% if n > 0 %}{{ countdown(n - 1) }}{% endif %}{% endmacro %}{{ countdown(3) }}",
+        "3210",
+        None,
+    )
+}
+
+#[test]
+fn macro_does_not_leak_params() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("value".to_string(), Value::Integer(5));
+    assert_render_template_eq(
+        "{% macro show(value) %}{{ value }}{% endmacro %}{{ show(1) }}{{ value }}",
+        "15",
+        Some(context),
+    )
+}
+
+#[test]
+fn macro_call_with_unpacked_args() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro link(url, text, target=\"_self\") %}<a href=\"{{ url }}\" target=\"{{ target }}\">{{ text }}</a>{% endmacro %}\
+{% set args = [\"/home\", \"Home\"] %}{% set options = {\"target\": \"_blank\"} %}{{ link(*args, **options) }}",
+        "<a href=\"/home\" target=\"_blank\">Home</a>",
+        None,
+    )
+}
+
+#[test]
+fn macro_call_mixes_unpacked_and_explicit_args() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro list(first) %}{{ first }}{% for arg in varargs %},{{ arg }}{% endfor %}|{{ kwargs.sep }}{% endmacro %}\
+{{ list(0, *[1, 2], sep=\";\") }}",
+        "0,1,2|;",
+        None,
+    )
+}
+
+#[test]
+fn error_macro_call_unpacking_non_list() -> Result<()> {
+    let result = assert_render_template_eq("{% macro m() %}{% endmacro %}{{ m(*42) }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Invalid type of the value in the particular context".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_macro_call_unpacking_twice() -> Result<()> {
+    let result =
+        assert_render_template_eq("{% macro m() %}{% endmacro %}{{ m(*[1], *[2]) }}", "", None);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn error_macro_argument_given_twice() -> Result<()> {
+    let result = assert_render_template_eq(
+        "{% macro m(a) %}{{ a }}|{{ kwargs }}{% endmacro %}{{ m(1, a=2) }}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Multiple values for argument 'a'".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_macro_missing_argument() -> Result<()> {
+    let result = assert_render_template_eq(
+        "{% macro m(a, b=2) %}{{ a }}{{ b }}{% endmacro %}{{ m(b=3) }}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Missing argument 'a'".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_macro_too_many_arguments() -> Result<()> {
+    let result = assert_render_template_eq(
+        "{% macro m(a) %}{{ a }}{% endmacro %}{{ m(1, 2) }}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Too many arguments for 'm'".to_string()
+    );
+    let result = assert_render_template_eq(
+        "{% macro m() %}{% call(x) n() %}{{ x }}{% endcall %}{% endmacro %}\
+         {% macro n() %}{{ caller(1, 2) }}{% endmacro %}{{ m() }}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Too many arguments for 'caller'".to_string()
+    );
+    // The varargs of a nested macro don't let the outer macro take extra arguments
+    let result = assert_render_template_eq(
+        "{% macro m() %}{% macro n() %}{{ varargs }}{% endmacro %}{% endmacro %}{{ m(1) }}",
+        "",
+        None,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Too many arguments for 'm'".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn macro_varargs_in_call_block() -> Result<()> {
+    assert_render_template_eq(
+        "{% macro n() %}{{ caller(1, 2, 3) }}{% endmacro %}\
+         {% call(x) n() %}{{ x }}:{{ varargs | length }}{% endcall %}",
+        "1:2",
+        None,
+    )
+}
+
+#[test]
+fn error_macro_missing_bracket() -> Result<()> {
+    let result = assert_render_template_eq("{% macro hello %}{% endmacro %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:15: error: '(' expected".to_string()
+    );
+    Ok(())
+}
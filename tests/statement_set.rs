@@ -0,0 +1,90 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+
+#[test]
+fn set_simple() -> Result<()> {
+    assert_render_template_eq("{% set x = 40 + 2 %}{{ x }}", "42", None)
+}
+
+#[test]
+fn set_overrides_context() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert("x".to_string(), Value::Integer(1));
+    assert_render_template_eq("{{ x }}{% set x = 2 %}{{ x }}", "12", Some(context))
+}
+
+#[test]
+fn set_tuple_unpacking() -> Result<()> {
+    assert_render_template_eq(
+        "{% set a, b = (\"first\", \"second\") %}{{ b }} {{ a }}",
+        "second first",
+        None,
+    )
+}
+
+#[test]
+fn set_block() -> Result<()> {
+    assert_render_template_eq(
+        "{% set greeting %}Hello {{ \"World\" }}!{% endset %}[{{ greeting }}]",
+        "[Hello World!]",
+        None,
+    )
+}
+
+#[test]
+fn set_block_filtered() -> Result<()> {
+    assert_render_template_eq(
+        "{% set greeting | upper | truncate(5) %}Hello World{% endset %}{{ greeting }}",
+        "HE...",
+        None,
+    )
+}
+
+#[test]
+fn set_in_if_is_visible_outside() -> Result<()> {
+    assert_render_template_eq("{% if true %}{% set x = 1 %}{% endif %}{{ x }}", "1", None)
+}
+
+#[test]
+fn set_in_for_does_not_leak() -> Result<()> {
+    let mut context = ValuesMap::default();
+    context.insert(
+        "items".to_string(),
+        Value::ValuesList(vec![Value::Integer(1), Value::Integer(2)]),
+    );
+    assert_render_template_eq(
+        "{% set last = 0 %}{% for item in items %}{% set last = item %}{{ last }}{% endfor %}{{ last }}",
+        "120",
+        Some(context),
+    )
+}
+
+#[test]
+fn error_set_unpack_mismatch() -> Result<()> {
+    let result = assert_render_template_eq("{% set a, b = [1, 2, 3] %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Cannot unpack 3 values into 2 variables".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_set_block_with_many_vars() -> Result<()> {
+    let result = assert_render_template_eq("{% set a, b %}{% endset %}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl:1:12: error: Specific token expected (=)".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn set_nested_tuple_unpacking() -> Result<()> {
+    assert_render_template_eq(
+        "{% set a, (b, c) = [1, [2, 3]] %}{{ a }}{{ b }}{{ c }}",
+        "123",
+        None,
+    )
+}
@@ -0,0 +1,144 @@
+use super::utils::assert_render_template_eq;
+use temple::error::Result;
+use temple::value::{Value, ValuesMap};
+
+fn context() -> ValuesMap {
+    let mut user = ValuesMap::default();
+    user.insert("name".to_string(), Value::String("John".to_string()));
+    let mut context = ValuesMap::default();
+    context.insert("user".to_string(), Value::ValuesMap(user));
+    context.insert("nothing".to_string(), Value::None);
+    context
+}
+
+#[test]
+fn tester_defined() -> Result<()> {
+    assert_render_template_eq("{{ user is defined }}", "true", Some(context()))?;
+    assert_render_template_eq("{{ missing_value is defined }}", "false", None)?;
+    assert_render_template_eq("{{ user.name is defined }}", "true", Some(context()))?;
+    assert_render_template_eq("{{ user.email is defined }}", "false", Some(context()))?;
+    assert_render_template_eq("{{ [1, 2][5] is defined }}", "false", None)?;
+    assert_render_template_eq("{{ missing_value is undefined }}", "true", None)?;
+    assert_render_template_eq("{{ user is not defined }}", "false", Some(context()))
+}
+
+#[test]
+fn tester_defined_guards_optional_values() -> Result<()> {
+    assert_render_template_eq(
+        "{% if user.email is defined %}{{ user.email }}{% else %}{{ user.name }}{% endif %}",
+        "John",
+        Some(context()),
+    )
+}
+
+#[test]
+fn tester_types() -> Result<()> {
+    assert_render_template_eq("{{ nothing is none }}", "true", Some(context()))?;
+    assert_render_template_eq("{{ None is none }}", "true", None)?;
+    assert_render_template_eq("{{ 0 is none }}", "false", None)?;
+    assert_render_template_eq("{{ false is boolean }}", "true", None)?;
+    assert_render_template_eq("{{ 1.5 is number }}", "true", None)?;
+    assert_render_template_eq("{{ \"1\" is number }}", "false", None)?;
+    assert_render_template_eq("{{ \"text\" is string }}", "true", None)?;
+    assert_render_template_eq("{{ user is mapping }}", "true", Some(context()))?;
+    assert_render_template_eq("{{ [1] is mapping }}", "false", None)?;
+    assert_render_template_eq("{{ [1] is iterable }}", "true", None)?;
+    assert_render_template_eq("{{ 1 is iterable }}", "false", None)?;
+    assert_render_template_eq("{{ \"abc\" is sequence }}", "true", None)
+}
+
+#[test]
+fn tester_numbers() -> Result<()> {
+    assert_render_template_eq("{{ 3 is odd }}", "true", None)?;
+    assert_render_template_eq("{{ 3 is even }}", "false", None)?;
+    assert_render_template_eq("{{ 9 is divisibleby 3 }}", "true", None)?;
+    assert_render_template_eq("{{ 9 is divisibleby(4) }}", "false", None)?;
+    assert_render_template_eq("{{ 9 is not divisibleby(4) }}", "true", None)
+}
+
+#[test]
+fn tester_comparisons() -> Result<()> {
+    assert_render_template_eq("{{ 2 is eq 2 }}", "true", None)?;
+    assert_render_template_eq("{{ 2 is equalto(3) }}", "false", None)?;
+    assert_render_template_eq("{{ 2 is ne 3 }}", "true", None)?;
+    assert_render_template_eq("{{ 2 is lt 3 }}", "true", None)?;
+    assert_render_template_eq("{{ 2 is gt 3 }}", "false", None)?;
+    assert_render_template_eq("{{ 3 is ge 3 }}", "true", None)?;
+    assert_render_template_eq("{{ 4 is le 3 }}", "false", None)
+}
+
+#[test]
+fn tester_in() -> Result<()> {
+    assert_render_template_eq("{{ 2 is in [1, 2, 3] }}", "true", None)?;
+    assert_render_template_eq("{{ \"name\" is in user }}", "true", Some(context()))?;
+    assert_render_template_eq("{{ \"ell\" is in(\"hello\") }}", "true", None)?;
+    assert_render_template_eq("{{ 5 is not in [1, 2, 3] }}", "true", None)
+}
+
+#[test]
+fn tester_case() -> Result<()> {
+    assert_render_template_eq("{{ \"hello world\" is lower }}", "true", None)?;
+    assert_render_template_eq("{{ \"Hello\" is lower }}", "false", None)?;
+    assert_render_template_eq("{{ \"HELLO 1\" is upper }}", "true", None)?;
+    assert_render_template_eq("{{ \"123\" is upper }}", "false", None)
+}
+
+#[test]
+fn tester_sameas() -> Result<()> {
+    assert_render_template_eq("{{ false is sameas false }}", "true", None)?;
+    assert_render_template_eq("{{ 0 is sameas false }}", "false", None)?;
+    assert_render_template_eq("{{ 1 is sameas 1.0 }}", "false", None)?;
+    assert_render_template_eq("{{ nothing is sameas None }}", "true", Some(context()))?;
+    assert_render_template_eq("{% set xs = [1, 2] %}{{ xs is sameas xs }}", "true", None)?;
+    assert_render_template_eq(
+        "{% set xs = {\"a\": 1} %}{{ xs is sameas xs }}",
+        "true",
+        None,
+    )?;
+    assert_render_template_eq("{{ [1, 2] is sameas [2, 1] }}", "false", None)
+}
+
+#[test]
+fn tester_parity_of_non_integers() -> Result<()> {
+    assert_render_template_eq("{{ 1.5 is odd }}", "false", None)?;
+    assert_render_template_eq("{{ 1.5 is even }}", "false", None)?;
+    assert_render_template_eq("{{ \"a\" is odd }}", "false", None)?;
+    assert_render_template_eq("{{ (-3) is odd }}", "true", None)
+}
+
+#[test]
+fn tester_precedence() -> Result<()> {
+    assert_render_template_eq("{{ not 3 is odd }}", "false", None)?;
+    assert_render_template_eq("{{ \"a\" | upper is upper }}", "true", None)?;
+    assert_render_template_eq("{{ 3 is odd and 4 is even }}", "true", None)?;
+    assert_render_template_eq("{{ \"odd\" if 3 is odd else \"even\" }}", "odd", None)
+}
+
+#[test]
+fn tester_in_loop() -> Result<()> {
+    assert_render_template_eq(
+        "{% for x in [1, 2, 3, 4] if x is even %}{{ x }}{% if loop.nextitem is defined %},{% endif %}{% endfor %}",
+        "2,4",
+        None,
+    )
+}
+
+#[test]
+fn error_unknown_tester() -> Result<()> {
+    let result = assert_render_template_eq("{{ 1 is unknown }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "noname.j2tpl: error: Unknown test: unknown".to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn error_tester_missing_argument() -> Result<()> {
+    let result = assert_render_template_eq("{{ 1 is divisibleby }}", "", None);
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Missing argument 'num'".to_string()
+    );
+    Ok(())
+}
@@ -16,3 +16,19 @@ pub fn assert_render_template_eq(
     assert_eq!(result, expected.to_string());
     Ok(())
 }
+
+pub fn assert_render_template_with_do_eq(
+    input: &str,
+    expected: &str,
+    params: Option<ValuesMap>,
+) -> Result<()> {
+    let mut temp_env = TemplateEnv::default();
+    temp_env.settings_mut().extensions.do_ext = true;
+    let mut template = Template::new(&temp_env)?;
+    template.load(input)?;
+    let default_context = ValuesMap::default();
+    let context = params.unwrap_or(default_context);
+    let result = template.render_as_string(context)?;
+    assert_eq!(result, expected.to_string());
+    Ok(())
+}